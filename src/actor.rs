@@ -0,0 +1,115 @@
+//! Runs a conversation on its own task and talks to it through messages
+//!
+//! Useful for GUIs and games that can't hold `&mut Conversation` across awaits.
+//! Sending a command never blocks, and the reply arrives on a oneshot channel that
+//! can be awaited or checked with `try_recv` every frame.
+//! ## Example:
+//! ```rust,no_run
+//! # use gemini_rs::{Conversation, Part, actor::ConversationActor};
+//! # async fn run() {
+//! let convo = Conversation::new(std::env::var("GEMINI_API_KEY").unwrap(), "gemini-1.5-flash".to_string());
+//! let actor = ConversationActor::spawn(convo);
+//! let reply = actor.send_message(vec![Part::Text("Tell me a story".to_string())]);
+//! // The user pressed stop
+//! actor.cancel();
+//! assert!(matches!(reply.await, Ok(Err(gemini_rs::GeminiError::Cancelled))));
+//! # }
+//! ```
+use std::collections::VecDeque;
+
+use tokio::sync::{mpsc, oneshot};
+
+use crate::{response::GeminiResponse, Conversation, GeminiError, Message, Part};
+
+/// A reply to [Command::SendMessage]
+pub type Reply = oneshot::Receiver<Result<GeminiResponse, GeminiError<'static>>>;
+
+/// Something for the actor to do
+#[derive(Debug)]
+pub enum Command {
+    /// Prompts the conversation and sends back the response. Messages are handled
+    /// one at a time, in order.
+    SendMessage(Vec<Part>, oneshot::Sender<Result<GeminiResponse, GeminiError<'static>>>),
+    /// Stops the message being generated right now, which gets [GeminiError::Cancelled]
+    /// and is left out of the history. Queued messages still run.
+    Cancel,
+    /// Sends back a copy of the history. Waits for the current message if there is one.
+    GetHistory(oneshot::Sender<Vec<Message>>),
+}
+
+/// A cloneable handle to a conversation running on its own task. The task stops
+/// once every handle is dropped and the queued messages are done.
+#[derive(Debug, Clone)]
+pub struct ConversationActor {
+    sender: mpsc::UnboundedSender<Command>,
+} impl ConversationActor {
+    /// Moves the conversation onto a new task. Must be called from inside a tokio runtime.
+    pub fn spawn(conversation: Conversation) -> Self {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        tokio::spawn(run(conversation, receiver));
+        Self { sender }
+    }
+
+    /// Sends a command. Replies to commands sent after the actor stopped are dropped.
+    pub fn send(&self, command: Command) {
+        let _ = self.sender.send(command);
+    }
+
+    pub fn send_message(&self, input: Vec<Part>) -> Reply {
+        let (reply, receiver) = oneshot::channel();
+        self.send(Command::SendMessage(input, reply));
+        receiver
+    }
+
+    pub fn cancel(&self) {
+        self.send(Command::Cancel);
+    }
+
+    pub fn get_history(&self) -> oneshot::Receiver<Vec<Message>> {
+        let (reply, receiver) = oneshot::channel();
+        self.send(Command::GetHistory(reply));
+        receiver
+    }
+}
+
+async fn run(mut conversation: Conversation, mut receiver: mpsc::UnboundedReceiver<Command>) {
+    let mut queued = VecDeque::new();
+    loop {
+        let command = match queued.pop_front() {
+            Some(command) => command,
+            None => match receiver.recv().await {
+                Some(command) => command,
+                None => break
+            }
+        };
+        match command {
+            Command::SendMessage(input, reply) => {
+                let history_len = conversation.history.len();
+                let result = {
+                    let generation = conversation.generate_content(input);
+                    tokio::pin!(generation);
+                    loop {
+                        tokio::select! {
+                            result = &mut generation => break Some(result),
+                            command = receiver.recv() => match command {
+                                Some(Command::Cancel) => break None,
+                                Some(command) => queued.push_back(command),
+                                // Every handle is gone, but someone may still be waiting for this reply
+                                None => break Some((&mut generation).await),
+                            }
+                        }
+                    }
+                };
+                let result = result.unwrap_or_else(|| {
+                    conversation.history.truncate(history_len);
+                    Err(GeminiError::Cancelled)
+                });
+                let _ = reply.send(result);
+            },
+            Command::Cancel => {},
+            Command::GetHistory(reply) => {
+                let _ = reply.send(conversation.get_history().to_vec());
+            },
+        }
+    }
+}
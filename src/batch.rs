@@ -0,0 +1,224 @@
+//! Writes prompts and their results as JSON lines (NDJSON), and reads them back to
+//! resume a batch run that was interrupted
+//!
+//! Each line holds one record, so a crash loses at most the line being written.
+//! ## Example:
+//! ```rust,no_run
+//! # use gemini_rs::{Conversation, Part, batch::{self, BatchRecord, NdjsonWriter}};
+//! # async fn run() -> Result<(), gemini_rs::GeminiError<'static>> {
+//! let api_key = std::env::var("GEMINI_API_KEY").unwrap();
+//! let done = batch::completed_ids("results.jsonl")?;
+//! let mut writer = NdjsonWriter::append("results.jsonl")?;
+//! for (id, text) in [("1", "Summarize Hamlet"), ("2", "Summarize Macbeth")] {
+//!     if done.contains(id) { continue }
+//!     let mut convo = Conversation::new(api_key.clone(), "gemini-1.5-flash".to_string());
+//!     let prompt = vec![Part::Text(text.to_string())];
+//!     let started = std::time::Instant::now();
+//!     let result = convo.generate_content(prompt.clone()).await;
+//!     writer.write(&BatchRecord::new(id, convo.get_model(), prompt, &result, started.elapsed()))?;
+//! }
+//! # Ok(())
+//! # }
+//! ```
+use std::{collections::HashSet, fs::{File, OpenOptions}, io::{BufWriter, Write}, path::Path, time::Duration};
+
+use json::JsonValue;
+
+use crate::{response::GeminiResponse, GeminiError, Message, Part};
+
+/// One prompt and what came of it
+#[derive(Debug, Clone)]
+pub struct BatchRecord {
+    /// Identifies the prompt within the batch, used to skip it when resuming
+    pub id: String,
+    pub model: String,
+    pub prompt: Vec<Part>,
+    /// The content of the response, `None` if the request failed
+    pub response: Option<Vec<Part>>,
+    /// The error message if the request failed
+    pub error: Option<String>,
+    pub request_id: Option<String>,
+    pub output_tokens: u64,
+    pub duration: Duration,
+} impl BatchRecord {
+    pub fn new(
+        id: &str,
+        model: &str,
+        prompt: Vec<Part>,
+        result: &Result<GeminiResponse, GeminiError<'_>>,
+        duration: Duration
+    ) -> Self {
+        let (response, error, request_id, output_tokens) = match result {
+            Ok(response) => (Some(response.content.clone()), None, Some(response.request_id.clone()), response.token_count),
+            Err(e) => (None, Some(e.to_string_without_url()), None, 0),
+        };
+        Self {
+            id: id.to_string(),
+            model: model.to_string(),
+            prompt,
+            response,
+            error,
+            request_id,
+            output_tokens,
+            duration,
+        }
+    }
+
+    /// Whether the request succeeded, failed records are retried when resuming
+    pub fn is_complete(&self) -> bool {
+        self.response.is_some()
+    }
+
+    pub fn get_real(&self) -> JsonValue {
+        let parts = |content: &Vec<Part>| {
+            Message { content: content.clone(), role: String::new() }.get_real()["parts"].clone()
+        };
+        let mut record = json::object! {
+            "id": self.id.as_str(),
+            "model": self.model.as_str(),
+            "prompt": parts(&self.prompt),
+            "usage": { "outputTokens": self.output_tokens },
+            "durationMs": self.duration.as_millis() as u64
+        };
+        if let Some(response) = &self.response {
+            record["response"] = parts(response);
+        }
+        if let Some(error) = &self.error {
+            record["error"] = error.as_str().into();
+        }
+        if let Some(request_id) = &self.request_id {
+            record["requestId"] = request_id.as_str().into();
+        }
+        record
+    }
+
+    pub fn get_fake(input: &JsonValue) -> BatchRecord {
+        let parts = |parts: &JsonValue| Message::get_fake(&json::object! { "parts": parts.clone() }).content;
+        let string = |key: &str| input[key].as_str().map(str::to_string);
+        BatchRecord {
+            id: string("id").unwrap_or_default(),
+            model: string("model").unwrap_or_default(),
+            prompt: parts(&input["prompt"]),
+            response: input["response"].is_array().then(|| parts(&input["response"])),
+            error: string("error"),
+            request_id: string("requestId"),
+            output_tokens: input["usage"]["outputTokens"].as_u64().unwrap_or(0),
+            duration: Duration::from_millis(input["durationMs"].as_u64().unwrap_or(0)),
+        }
+    }
+}
+
+/// Writes one [BatchRecord] per line, flushing after each one
+#[derive(Debug)]
+pub struct NdjsonWriter<W: Write> {
+    writer: W,
+} impl<W: Write> NdjsonWriter<W> {
+    pub fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn write(&mut self, record: &BatchRecord) -> Result<(), GeminiError<'static>> {
+        writeln!(self.writer, "{0}", record.get_real().dump())?;
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+} impl NdjsonWriter<BufWriter<File>> {
+    /// Opens `path` for appending, creating it if needed
+    pub fn append(path: impl AsRef<Path>) -> Result<Self, GeminiError<'static>> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self::new(BufWriter::new(file)))
+    }
+}
+
+/// Reads every record from NDJSON text. Blank lines and a cut off last line (from a
+/// crash while writing) are skipped, a broken line anywhere else is an error.
+pub fn parse_records(input: &str) -> Result<Vec<BatchRecord>, GeminiError<'static>> {
+    let lines: Vec<&str> = input.lines().filter(|i| !i.trim().is_empty()).collect();
+    let mut records = vec![];
+    for (i, line) in lines.iter().enumerate() {
+        match json::parse(line) {
+            Ok(json) => records.push(BatchRecord::get_fake(&json)),
+            Err(_) if i + 1 == lines.len() => break,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    Ok(records)
+}
+
+/// Reads every record from an NDJSON file, see [parse_records]. A missing file has no records.
+pub fn read_records(path: impl AsRef<Path>) -> Result<Vec<BatchRecord>, GeminiError<'static>> {
+    match std::fs::read_to_string(path) {
+        Ok(input) => parse_records(&input),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The ids of records that completed, to skip when resuming a batch
+pub fn completed_ids(path: impl AsRef<Path>) -> Result<HashSet<String>, GeminiError<'static>> {
+    Ok(read_records(path)?.into_iter().filter(BatchRecord::is_complete).map(|i| i.id).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(id: &str, result: Result<&str, GeminiError<'static>>) -> BatchRecord {
+        let prompt = vec![Part::Text("prompt".to_string())];
+        match result {
+            Ok(text) => BatchRecord {
+                id: id.to_string(),
+                model: "gemini-1.5-flash".to_string(),
+                prompt,
+                response: Some(vec![Part::Text(text.to_string())]),
+                error: None,
+                request_id: Some(format!("request-{id}")),
+                output_tokens: 3,
+                duration: Duration::from_millis(20),
+            },
+            Err(e) => BatchRecord::new(id, "gemini-1.5-flash", prompt, &Err(e), Duration::from_millis(20)),
+        }
+    }
+
+    #[test]
+    fn completed_ids_skips_failures_and_truncated_lines() {
+        let path = std::env::temp_dir().join(format!("gemini-rs-batch-test-{0}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        assert!(completed_ids(&path).unwrap().is_empty());
+
+        let mut writer = NdjsonWriter::append(&path).unwrap();
+        writer.write(&record("1", Ok("done"))).unwrap();
+        writer.write(&record("2", Err(GeminiError::Cancelled))).unwrap();
+        writer.write(&record("3", Ok("done too"))).unwrap();
+        drop(writer);
+        // A crash in the middle of writing record 4
+        let line = record("4", Ok("cut off")).get_real().dump();
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        write!(file, "{0}", &line[..line.len() / 2]).unwrap();
+
+        let ids = completed_ids(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(ids, HashSet::from(["1".to_string(), "3".to_string()]));
+    }
+
+    #[test]
+    fn keeps_failed_records() {
+        let failed = record("2", Err(GeminiError::Cancelled));
+        let records = parse_records(&format!("{0}\n\n", failed.get_real().dump())).unwrap();
+        assert_eq!(records.len(), 1);
+        assert!(!records[0].is_complete());
+        assert_eq!(records[0].error, failed.error);
+        assert_eq!(records[0].request_id, None);
+    }
+
+    #[test]
+    fn rejects_broken_lines_before_the_last() {
+        let good = record("1", Ok("done")).get_real().dump();
+        assert!(parse_records(&format!("{{\"id\": \n{good}")).is_err());
+        assert_eq!(parse_records(&format!("{good}\n{{\"id\": ")).unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,123 @@
+//! A small command line client built on this crate (requires the `cli` feature)
+//!
+//! ```text
+//! gemini [options] <prompt>    send one prompt and print the reply
+//! gemini [options] chat        chat interactively, see gemini_rs::repl for the commands
+//! gemini models                list the available models
+//!
+//! options:
+//!   -m, --model <model>        model to use (default gemini-1.5-flash)
+//!   -f, --file <path>          attach a file, can be repeated
+//!   -H, --history <path>       load the chat history from this file and save it after every reply
+//! ```
+//!
+//! The API key is found with [gemini_rs::credentials::api_key].
+use std::{io, path::Path, process::ExitCode};
+
+use gemini_rs::{credentials, files, repl::Repl, Conversation, Part};
+
+const USAGE: &str = "usage: gemini [-m model] [-f file]... [-H history.json] (<prompt> | chat | models)";
+
+struct Args {
+    model: String,
+    files: Vec<String>,
+    history: Option<String>,
+    command: Vec<String>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        model: "gemini-1.5-flash".to_string(),
+        files: vec![],
+        history: None,
+        command: vec![],
+    };
+    let mut input = std::env::args().skip(1);
+    while let Some(arg) = input.next() {
+        let mut value = || input.next().ok_or(format!("{arg} needs a value"));
+        match arg.as_str() {
+            "-m" | "--model" => args.model = value()?,
+            "-f" | "--file" => args.files.push(value()?),
+            "-H" | "--history" => args.history = Some(value()?),
+            "-h" | "--help" => return Err(USAGE.to_string()),
+            _ => args.command.push(arg),
+        }
+    }
+    if args.command.is_empty() {
+        return Err(USAGE.to_string())
+    }
+    Ok(args)
+}
+
+async fn attachments(paths: &[String], token: &str) -> Result<Vec<Part>, String> {
+    let mut parts = vec![];
+    for path in paths {
+        let mime_type = files::mime_type_for_path(path)
+            .ok_or_else(|| format!("{path}: unsupported file type"))?;
+        let part = files::attach_file(path, mime_type, token, files::DEFAULT_INLINE_LIMIT)
+            .await
+            .map_err(|e| format!("{path}: {e}"))?;
+        parts.push(part);
+    }
+    Ok(parts)
+}
+
+async fn run(args: Args, token: String) -> Result<(), String> {
+    if args.command == ["models"] {
+        for model in gemini_rs::get_models(&token).await.map_err(|e| e.to_string())? {
+            println!("{model}");
+        }
+        return Ok(())
+    }
+
+    let mut convo = Conversation::new(token.clone(), args.model.clone());
+    let input = attachments(&args.files, &token).await?;
+
+    if args.command == ["chat"] {
+        // Attachments only go with the first message
+        let mut repl = Repl::new(&mut convo).attach(input);
+        if let Some(path) = &args.history {
+            repl = repl.history_file(path);
+        }
+        return repl.run(io::stdin().lock(), io::stdout()).await.map_err(|e| e.to_string())
+    }
+
+    if let Some(path) = &args.history {
+        if Path::new(path).exists() {
+            convo.try_load(path).map_err(|e| format!("{path}: {e}"))?;
+        }
+    }
+    let mut input = input;
+    input.push(Part::Text(args.command.join(" ")));
+    let response = convo.generate_content(input).await.map_err(|e| e.to_string())?;
+    println!("{response}");
+    if let Some(path) = &args.history {
+        convo.try_save(path).map_err(|e| format!("{path}: {e}"))?;
+    }
+    Ok(())
+}
+
+#[tokio::main]
+async fn main() -> ExitCode {
+    let args = match parse_args() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE
+        }
+    };
+    let token = match credentials::api_key() {
+        Ok(token) => token,
+        Err(e) => {
+            eprintln!("{e}");
+            return ExitCode::FAILURE
+        }
+    };
+    match run(args, token).await {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("error: {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
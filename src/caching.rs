@@ -0,0 +1,122 @@
+//! Caches the start of a conversation with the `cachedContents` API, so long
+//! instructions or documents at the start of the history aren't billed in full on
+//! every request
+//!
+//! ## Example:
+//! ```rust,no_run
+//! # use std::time::Duration;
+//! # use gemini_rs::{Conversation, Part};
+//! # async fn run() -> Result<(), gemini_rs::GeminiError<'static>> {
+//! let api_key = std::env::var("GEMINI_API_KEY").unwrap();
+//! let mut convo = Conversation::new(api_key, "gemini-1.5-flash-002".to_string());
+//! let manual = std::fs::read_to_string("manual.txt")?;
+//! convo.extend_history(vec![gemini_rs::Message {
+//!     content: vec![Part::Text("Answer questions about this manual".to_string()), Part::Text(manual)],
+//!     role: "user".to_string(),
+//! }]);
+//! convo.cache_context(Duration::from_secs(600)).await?;
+//! convo.generate_content(vec![Part::Text("How do I reset it?".to_string())]).await?;
+//! convo.uncache_context().await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::time::{Duration, Instant};
+
+use reqwest::Method;
+
+use crate::{check_api_error, Conversation, GeminiError};
+
+/// A cached prefix of a conversation's history
+#[derive(Debug, Clone)]
+pub(crate) struct CachedContext {
+    /// The resource name, e.g. `cachedContents/abc123`
+    pub(crate) name: String,
+    /// How many messages at the start of the history are cached
+    pub(crate) messages: usize,
+    pub(crate) ttl: Duration,
+    pub(crate) refreshed: Instant,
+}
+
+impl Conversation {
+    /// Caches the current history for `ttl`. Following requests only send the
+    /// messages added after this, and the cache is refreshed automatically while
+    /// the conversation keeps being used. Any previous cache is replaced.
+    ///
+    /// The API needs a minimum number of tokens to cache and a model version that
+    /// supports caching, otherwise this returns an [GeminiError::ApiError].
+    pub async fn cache_context(&mut self, ttl: Duration) -> Result<(), GeminiError<'static>> {
+        self.uncache_context().await?;
+        let mut body = json::object! {
+            "model": format!("models/{0}", self.model),
+            "contents": [],
+            "ttl": format!("{0}s", ttl.as_secs())
+        };
+        for i in &self.history {
+            body["contents"].push(i.get_real())?;
+        }
+        if let Some(instruction) = self.system_instruction_json() {
+            body["systemInstruction"] = instruction;
+        }
+        let response = self.cache_request(Method::POST, "cachedContents", Some(body)).await?;
+        let Some(name) = response["name"].as_str() else {
+            return Err(GeminiError::ParseError("Failed to extract the cached content name"))
+        };
+        self.cached_context = Some(CachedContext {
+            name: name.to_string(),
+            messages: self.history.len(),
+            ttl,
+            refreshed: Instant::now(),
+        });
+        Ok(())
+    }
+
+    /// Deletes the cache made by [Conversation::cache_context], if there is one. The
+    /// whole history is sent with every request again.
+    pub async fn uncache_context(&mut self) -> Result<(), GeminiError<'static>> {
+        if let Some(cached) = self.cached_context.take() {
+            self.cache_request(Method::DELETE, &cached.name, None).await?;
+        }
+        Ok(())
+    }
+
+    /// The name of the cached content in use, if any
+    pub fn cached_context_name(&self) -> Option<&str> {
+        self.cached_context.as_ref().map(|i| i.name.as_str())
+    }
+
+    /// How many messages at the start of the history are left out of requests
+    pub(crate) fn cached_messages(&self) -> usize {
+        match &self.cached_context {
+            // The history was replaced, so the cache no longer matches it
+            Some(cached) if cached.messages <= self.history.len() => cached.messages,
+            _ => 0
+        }
+    }
+
+    /// Extends the cache's ttl once more than half of it has passed
+    pub(crate) async fn refresh_cached_context(&mut self) -> Result<(), GeminiError<'static>> {
+        let Some(cached) = &self.cached_context else { return Ok(()) };
+        if cached.refreshed.elapsed() < cached.ttl / 2 { return Ok(()) }
+        let path = format!("{0}?updateMask=ttl", cached.name);
+        let body = json::object! { "ttl": format!("{0}s", cached.ttl.as_secs()) };
+        self.cache_request(Method::PATCH, &path, Some(body)).await?;
+        if let Some(cached) = &mut self.cached_context {
+            cached.refreshed = Instant::now();
+        }
+        Ok(())
+    }
+
+    async fn cache_request(&self, method: Method, path: &str, body: Option<json::JsonValue>) -> Result<json::JsonValue, GeminiError<'static>> {
+        let mut request = self.client
+            .request(method, format!("https://generativelanguage.googleapis.com/v1beta/{path}"))
+            .header(crate::API_KEY_HEADER, &self.token);
+        if let Some(body) = body {
+            request = request.header("Content-Type", "application/json").body(body.dump());
+        }
+        let text = request.send().await?.text().await?;
+        // DELETE answers with an empty object, or nothing at all
+        let response = if text.trim().is_empty() { json::object! {} } else { json::parse(&text)? };
+        check_api_error(&response)?;
+        Ok(response)
+    }
+}
@@ -0,0 +1,193 @@
+//! Builds large prompt contexts out of files and strings
+//!
+//! Useful for "chat with my docs" style conversations, where the inputs can be
+//! bigger than what fits comfortably in a single part.
+use std::fs;
+
+use crate::{count_tokens, get_token_limits, GeminiError, Message, Part};
+
+/// A piece of text added to a [ContextBuilder]
+#[derive(Debug, Clone)]
+struct ContextInput {
+    name: String,
+    text: String,
+}
+
+/// The messages made by [ContextBuilder::build]
+#[derive(Debug)]
+pub struct Context {
+    /// Ready to be added with [crate::Conversation::extend_history]
+    pub messages: Vec<Message>,
+    pub total_tokens: u64,
+    pub input_token_limit: u64,
+    /// Human readable problems found while building, such as going over the model's input limit
+    pub warnings: Vec<String>,
+}
+
+/// Collects files and strings, counts their tokens, and splits anything too big
+/// into multiple messages
+/// ## Example:
+/// ```rust,no_run
+/// # use gemini_rs::{Conversation, context::ContextBuilder};
+/// # async fn run() {
+/// let api_key = std::env::var("GEMINI_API_KEY").unwrap();
+/// let context = ContextBuilder::new(&api_key, "gemini-1.5-flash")
+///     .file("README.md").unwrap()
+///     .text("notes", "Some extra notes")
+///     .build()
+///     .await
+///     .unwrap();
+/// for warning in &context.warnings {
+///     eprintln!("{warning}");
+/// }
+///
+/// let mut convo = Conversation::new(api_key.clone(), "gemini-1.5-flash".to_string());
+/// convo.extend_history(context.messages);
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct ContextBuilder<'a> {
+    token: &'a str,
+    model: &'a str,
+    max_message_tokens: u64,
+    inputs: Vec<ContextInput>,
+} impl<'a> ContextBuilder<'a> {
+    pub fn new(token: &'a str, model: &'a str) -> Self {
+        Self {
+            token,
+            model,
+            max_message_tokens: 32_000,
+            inputs: vec![],
+        }
+    }
+
+    /// Inputs with more tokens than this get split across multiple messages (default 32000)
+    pub fn max_message_tokens(mut self, max: u64) -> Self {
+        self.max_message_tokens = max.max(1);
+        self
+    }
+
+    /// Adds a string, `name` is used to label it in the prompt
+    pub fn text(mut self, name: &str, text: &str) -> Self {
+        self.inputs.push(ContextInput {
+            name: name.to_string(),
+            text: text.to_string(),
+        });
+        self
+    }
+
+    /// Adds a text file, labeled with its path
+    pub fn file(self, path: &str) -> Result<Self, GeminiError<'static>> {
+        let text = fs::read_to_string(path)?;
+        Ok(self.text(path, &text))
+    }
+
+    /// Counts the tokens of every input and turns them into messages
+    pub async fn build(self) -> Result<Context, GeminiError<'static>> {
+        let input_token_limit = get_token_limits(self.token, self.model).await?.input_token_limit;
+        let mut messages = vec![];
+        let mut total_tokens = 0;
+        let mut warnings = vec![];
+
+        for input in self.inputs {
+            let message = Message {
+                content: vec![Part::Text(format!("{0}:\n{1}", input.name, input.text))],
+                role: "user".to_string()
+            };
+            let tokens = count_tokens(self.token, self.model, std::slice::from_ref(&message)).await?;
+            total_tokens += tokens;
+
+            if tokens <= self.max_message_tokens {
+                messages.push(message);
+                continue
+            }
+            let chunk_count = tokens.div_ceil(self.max_message_tokens) as usize;
+            let chunks = split_text(&input.text, chunk_count);
+            let chunk_total = chunks.len();
+            for (i, chunk) in chunks.into_iter().enumerate() {
+                messages.push(Message {
+                    content: vec![Part::Text(format!("{0} (part {1}/{2}):\n{3}", input.name, i + 1, chunk_total, chunk))],
+                    role: "user".to_string()
+                });
+            }
+        }
+
+        if total_tokens > input_token_limit {
+            warnings.push(format!(
+                "Context is {total_tokens} tokens, which is over the {input_token_limit} token input limit of {0}",
+                self.model
+            ));
+        }
+
+        Ok(Context {
+            messages,
+            total_tokens,
+            input_token_limit,
+            warnings,
+        })
+    }
+}
+
+/// Splits `text` into about `count` similarly sized chunks, preferring to cut at line
+/// breaks, then at whitespace, and only then anywhere
+fn split_text(text: &str, count: usize) -> Vec<String> {
+    let target = text.len().div_ceil(count.max(1)).max(1);
+    let mut chunks = vec![];
+    let mut current = String::new();
+    for piece in text.split_inclusive('\n').flat_map(|line| split_long_line(line, target)) {
+        if !current.is_empty() && current.len() + piece.len() > target {
+            chunks.push(std::mem::take(&mut current));
+        }
+        current.push_str(piece);
+    }
+    if !current.is_empty() {
+        chunks.push(current);
+    }
+    chunks
+}
+
+/// Cuts a line longer than `target` bytes into pieces that aren't, at whitespace where
+/// possible and otherwise at char boundaries
+fn split_long_line(line: &str, target: usize) -> Vec<&str> {
+    if line.len() <= target {
+        return vec![line]
+    }
+    let mut pieces = vec![];
+    for mut word in line.split_inclusive(char::is_whitespace) {
+        while word.len() > target {
+            let mut end = target;
+            while !word.is_char_boundary(end) { end -= 1 }
+            // A single char can be longer than a tiny target
+            if end == 0 { end = word.chars().next().map_or(word.len(), char::len_utf8) }
+            pieces.push(&word[..end]);
+            word = &word[end..];
+        }
+        if !word.is_empty() {
+            pieces.push(word);
+        }
+    }
+    pieces
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_a_single_long_line() {
+        let line = "lorem ipsum dolor sit amet ".repeat(40);
+        let chunks = split_text(&line, 4);
+        let target = line.len().div_ceil(4);
+        assert!(chunks.len() >= 4);
+        assert!(chunks.iter().all(|i| i.len() <= target));
+        assert!(chunks.iter().all(|i| i.ends_with(' ')));
+        assert_eq!(chunks.concat(), line);
+
+        let minified = format!("{{{0}}}", "\"ключ\":1,".repeat(50));
+        let chunks = split_text(&minified, 3);
+        let target = minified.len().div_ceil(3);
+        assert!(chunks.len() >= 3);
+        assert!(chunks.iter().all(|i| i.len() <= target));
+        assert_eq!(chunks.concat(), minified);
+    }
+}
@@ -0,0 +1,99 @@
+//! Finds the API key without it having to be in code, shell history or project files
+//!
+//! [api_key] looks in these places, in order:
+//! 1. The `GEMINI_API_KEY` environment variable
+//! 2. The OS keychain, under the service `gemini-rs` (requires the `keyring` feature,
+//!    see [store_in_keyring])
+//! 3. `api_key = "..."` in `~/.config/gemini-rs/config.toml` (or under `$XDG_CONFIG_HOME`,
+//!    or `%APPDATA%` on Windows)
+//! ## Example:
+//! ```rust,no_run
+//! # use gemini_rs::{Conversation, credentials};
+//! let mut convo = Conversation::new(credentials::api_key().unwrap(), "gemini-1.5-flash".to_string());
+//! ```
+use std::path::PathBuf;
+
+use crate::GeminiError;
+
+/// The environment variable checked first by [api_key]
+pub const ENV_VAR: &str = "GEMINI_API_KEY";
+
+#[cfg(feature = "keyring")]
+const KEYRING_SERVICE: &str = "gemini-rs";
+#[cfg(feature = "keyring")]
+const KEYRING_USER: &str = "api_key";
+
+/// The first API key found, see the [module docs](self)
+pub fn api_key() -> Result<String, GeminiError<'static>> {
+    if let Some(key) = std::env::var(ENV_VAR).ok().filter(|i| !i.trim().is_empty()) {
+        return Ok(key)
+    }
+    #[cfg(feature = "keyring")]
+    if let Some(key) = from_keyring()? {
+        return Ok(key)
+    }
+    if let Some(key) = from_config_file()? {
+        return Ok(key)
+    }
+    Err(GeminiError::KeyError(format!(
+        "No API key found. Set {ENV_VAR}, or add api_key to {0}",
+        config_path().map_or("the config file".to_string(), |i| i.display().to_string())
+    )))
+}
+
+/// Where the config file is looked for, `None` if there is no home or config directory
+pub fn config_path() -> Option<PathBuf> {
+    let env_dir = |name: &str| std::env::var_os(name).filter(|i| !i.is_empty()).map(PathBuf::from);
+    let config_dir = if cfg!(windows) {
+        env_dir("APPDATA")
+    } else {
+        env_dir("XDG_CONFIG_HOME").or_else(|| env_dir("HOME").map(|i| i.join(".config")))
+    };
+    config_dir.map(|i| i.join("gemini-rs").join("config.toml"))
+}
+
+/// The `api_key` from the config file, if the file exists and has one
+pub fn from_config_file() -> Result<Option<String>, GeminiError<'static>> {
+    let Some(path) = config_path() else { return Ok(None) };
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    Ok(parse_config(&contents))
+}
+
+/// Reads a top level `api_key = "..."` line. Only this much of TOML is understood.
+fn parse_config(contents: &str) -> Option<String> {
+    for line in contents.lines() {
+        let line = line.trim();
+        // Keys after a table header belong to that table
+        if line.starts_with('[') { break }
+        let Some((key, value)) = line.split_once('=') else { continue };
+        if key.trim() != "api_key" { continue }
+        let value = value.trim();
+        let quote = value.chars().next().filter(|i| *i == '"' || *i == '\'')?;
+        let value = &value[1..];
+        return value.find(quote).map(|end| value[..end].to_string()).filter(|i| !i.is_empty())
+    }
+    None
+}
+
+/// The API key stored in the OS keychain, if there is one (requires the `keyring` feature)
+#[cfg(feature = "keyring")]
+pub fn from_keyring() -> Result<Option<String>, GeminiError<'static>> {
+    let entry = keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER).map_err(|e| GeminiError::KeyError(e.to_string()))?;
+    match entry.get_password() {
+        Ok(key) => Ok(Some(key)),
+        Err(keyring::Error::NoEntry) => Ok(None),
+        Err(e) => Err(GeminiError::KeyError(e.to_string())),
+    }
+}
+
+/// Saves the API key in the OS keychain for [api_key] to find (requires the `keyring` feature)
+#[cfg(feature = "keyring")]
+pub fn store_in_keyring(key: &str) -> Result<(), GeminiError<'static>> {
+    keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER)
+        .and_then(|entry| entry.set_password(key))
+        .map_err(|e| GeminiError::KeyError(e.to_string()))
+}
@@ -0,0 +1,77 @@
+//! Iterative image editing with models that can output images
+//!
+//! ## Example:
+//! ```rust,no_run
+//! # async fn run() -> Result<(), gemini_rs::GeminiError<'static>> {
+//! # use gemini_rs::editing::ImageSession;
+//! let mut session = ImageSession::new("ABC123".to_string(), "gemini-2.0-flash-exp".to_string());
+//! session.edit("Draw a cat sitting on a fence").await?;
+//! session.edit("Make it night time").await?;
+//! session.save("cat.png")?;
+//! # Ok(())
+//! # }
+//! ```
+use std::path::Path;
+
+use crate::{files::InlineData, response::GeminiResponse, Conversation, GeminiError, OutputKind, Part};
+
+/// Keeps the last generated image and sends it along with every edit instruction.
+/// Only the image is carried over, not the whole history, so requests stay small.
+#[derive(Debug)]
+pub struct ImageSession {
+    conversation: Conversation,
+    image: Option<InlineData>,
+} impl ImageSession {
+    pub fn new(token: String, model: String) -> Self {
+        let mut conversation = Conversation::new(token, model);
+        conversation.output(OutputKind::Image);
+        Self { conversation, image: None }
+    }
+
+    /// Starts editing an existing image, e.g. one from [crate::files::inline_file]
+    pub fn from_image(token: String, model: String, image: InlineData) -> Self {
+        let mut session = Self::new(token, model);
+        session.image = Some(image);
+        session
+    }
+
+    /// The conversation used to send requests, to change its settings
+    pub fn conversation_mut(&mut self) -> &mut Conversation {
+        &mut self.conversation
+    }
+
+    /// The latest image, `None` until one is generated or given
+    pub fn image(&self) -> Option<&InlineData> {
+        self.image.as_ref()
+    }
+
+    /// Sends `instruction` with the current image attached. If the response contains
+    /// an image it replaces the current one, otherwise the current image is kept.
+    pub async fn edit(&mut self, instruction: &str) -> Result<GeminiResponse, GeminiError<'static>> {
+        let mut input = vec![];
+        if let Some(image) = &self.image {
+            input.push(Part::InlineData(image.clone()));
+        }
+        input.push(Part::Text(instruction.to_string()));
+
+        self.conversation.history.clear();
+        let response = self.conversation.generate_content(input).await?;
+        let image = response.content.iter().rev().find_map(|part| match part {
+            Part::InlineData(inline) if inline.mime_type.starts_with("image/") => Some(inline.clone()),
+            _ => None
+        });
+        if image.is_some() {
+            self.image = image;
+        }
+        Ok(response)
+    }
+
+    /// Writes the current image to `path`
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), GeminiError<'static>> {
+        let Some(image) = &self.image else {
+            return Err(GeminiError::FileError("No image has been generated yet".to_string()))
+        };
+        std::fs::write(path, image.decode()?)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,351 @@
+//! Typed details from error responses returned by the API
+use std::{fmt, time::Duration};
+
+use json::JsonValue;
+
+/// The canonical status of an API error, from the `status` field of an error response
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Status {
+    Ok,
+    Cancelled,
+    Unknown,
+    InvalidArgument,
+    DeadlineExceeded,
+    NotFound,
+    AlreadyExists,
+    PermissionDenied,
+    Unauthenticated,
+    ResourceExhausted,
+    FailedPrecondition,
+    Aborted,
+    OutOfRange,
+    Unimplemented,
+    Internal,
+    Unavailable,
+    DataLoss,
+} impl Status {
+    pub fn get_real(&self) -> &'static str {
+        match self {
+            Self::Ok => "OK",
+            Self::Cancelled => "CANCELLED",
+            Self::Unknown => "UNKNOWN",
+            Self::InvalidArgument => "INVALID_ARGUMENT",
+            Self::DeadlineExceeded => "DEADLINE_EXCEEDED",
+            Self::NotFound => "NOT_FOUND",
+            Self::AlreadyExists => "ALREADY_EXISTS",
+            Self::PermissionDenied => "PERMISSION_DENIED",
+            Self::Unauthenticated => "UNAUTHENTICATED",
+            Self::ResourceExhausted => "RESOURCE_EXHAUSTED",
+            Self::FailedPrecondition => "FAILED_PRECONDITION",
+            Self::Aborted => "ABORTED",
+            Self::OutOfRange => "OUT_OF_RANGE",
+            Self::Unimplemented => "UNIMPLEMENTED",
+            Self::Internal => "INTERNAL",
+            Self::Unavailable => "UNAVAILABLE",
+            Self::DataLoss => "DATA_LOSS",
+        }
+    }
+
+    /// Statuses that aren't known yet become [Status::Unknown]
+    pub fn get_fake(input: &str) -> Status {
+        match input {
+            "OK" => Self::Ok,
+            "CANCELLED" => Self::Cancelled,
+            "INVALID_ARGUMENT" => Self::InvalidArgument,
+            "DEADLINE_EXCEEDED" => Self::DeadlineExceeded,
+            "NOT_FOUND" => Self::NotFound,
+            "ALREADY_EXISTS" => Self::AlreadyExists,
+            "PERMISSION_DENIED" => Self::PermissionDenied,
+            "UNAUTHENTICATED" => Self::Unauthenticated,
+            "RESOURCE_EXHAUSTED" => Self::ResourceExhausted,
+            "FAILED_PRECONDITION" => Self::FailedPrecondition,
+            "ABORTED" => Self::Aborted,
+            "OUT_OF_RANGE" => Self::OutOfRange,
+            "UNIMPLEMENTED" => Self::Unimplemented,
+            "INTERNAL" => Self::Internal,
+            "UNAVAILABLE" => Self::Unavailable,
+            "DATA_LOSS" => Self::DataLoss,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// The HTTP status code the API uses for this status
+    pub fn http_code(&self) -> u16 {
+        match self {
+            Self::Ok => 200,
+            Self::Cancelled => 499,
+            Self::Unknown | Self::Internal | Self::DataLoss => 500,
+            Self::InvalidArgument | Self::FailedPrecondition | Self::OutOfRange => 400,
+            Self::DeadlineExceeded => 504,
+            Self::NotFound => 404,
+            Self::AlreadyExists | Self::Aborted => 409,
+            Self::PermissionDenied => 403,
+            Self::Unauthenticated => 401,
+            Self::ResourceExhausted => 429,
+            Self::Unimplemented => 501,
+            Self::Unavailable => 503,
+        }
+    }
+
+    /// Best guess at the status for an HTTP status code, for errors without a `status` field
+    pub fn from_http_code(code: u16) -> Status {
+        match code {
+            200..=299 => Self::Ok,
+            400 => Self::InvalidArgument,
+            401 => Self::Unauthenticated,
+            403 => Self::PermissionDenied,
+            404 => Self::NotFound,
+            409 => Self::Aborted,
+            429 => Self::ResourceExhausted,
+            499 => Self::Cancelled,
+            501 => Self::Unimplemented,
+            503 => Self::Unavailable,
+            504 => Self::DeadlineExceeded,
+            _ => Self::Unknown,
+        }
+    }
+
+    /// Reads the status of an API `error` object, using its `code` when `status` is missing
+    pub fn from_error(error: &JsonValue) -> Status {
+        match error["status"].as_str() {
+            Some(status) => Self::get_fake(status),
+            None => Self::from_http_code(error["code"].as_u16().unwrap_or(0)),
+        }
+    }
+}
+
+/// An error returned by the API, e.g. for an invalid API key or a malformed request
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    /// The HTTP status code
+    pub code: u16,
+    pub status: Status,
+    pub message: String,
+    /// The `X-Request-Id` of the generateContent request that failed, see
+    /// [crate::Conversation::last_request_id]
+    pub request_id: Option<String>,
+} impl ApiError {
+    pub fn get_fake(error: &JsonValue) -> ApiError {
+        let status = Status::from_error(error);
+        ApiError {
+            code: error["code"].as_u16().unwrap_or(status.http_code()),
+            status,
+            message: error["message"].as_str().unwrap_or("").to_string(),
+            request_id: None,
+        }
+    }
+} impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{0} {1}: {2}", self.code, self.status.get_real(), self.message)?;
+        if let Some(request_id) = &self.request_id {
+            write!(f, " (request {request_id})")?;
+        }
+        Ok(())
+    }
+} impl std::error::Error for ApiError {}
+
+/// How often an exhausted quota resets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QuotaPeriod {
+    Minute,
+    Day,
+    /// The period couldn't be worked out from the quota id
+    Unknown,
+}
+
+/// A single quota that was exceeded
+#[derive(Debug, Clone)]
+pub struct QuotaViolation {
+    /// e.g. `generativelanguage.googleapis.com/generate_content_free_tier_requests`
+    pub quota_metric: String,
+    /// e.g. `GenerateRequestsPerMinutePerProjectPerModel-FreeTier`
+    pub quota_id: String,
+    /// The limit that was hit, if Google sent it
+    pub limit: Option<u64>,
+} impl QuotaViolation {
+    pub fn period(&self) -> QuotaPeriod {
+        if self.quota_id.contains("PerMinute") {
+            QuotaPeriod::Minute
+        } else if self.quota_id.contains("PerDay") {
+            QuotaPeriod::Day
+        } else {
+            QuotaPeriod::Unknown
+        }
+    }
+}
+
+/// Details of a `RESOURCE_EXHAUSTED` (429) error
+#[derive(Debug, Clone)]
+pub struct QuotaFailure {
+    pub message: String,
+    pub violations: Vec<QuotaViolation>,
+    /// How long Google asks to wait before trying again
+    pub retry_delay: Option<Duration>,
+    /// The `X-Request-Id` of the generateContent request that failed
+    pub request_id: Option<String>,
+} impl QuotaFailure {
+    /// Reads the quota details from an API `error` object, returns `None` if it
+    /// isn't a quota error
+    pub fn get_fake(error: &JsonValue) -> Option<QuotaFailure> {
+        let mut violations = vec![];
+        let mut retry_delay = None;
+        let mut is_quota_error = Status::from_error(error) == Status::ResourceExhausted;
+        for detail in error["details"].members() {
+            match detail["@type"].as_str().unwrap_or("") {
+                "type.googleapis.com/google.rpc.QuotaFailure" => {
+                    is_quota_error = true;
+                    for i in detail["violations"].members() {
+                        violations.push(QuotaViolation {
+                            quota_metric: i["quotaMetric"].as_str().unwrap_or("").to_string(),
+                            quota_id: i["quotaId"].as_str().unwrap_or("").to_string(),
+                            limit: i["quotaValue"].as_str().and_then(|v| v.parse().ok()),
+                        })
+                    }
+                },
+                "type.googleapis.com/google.rpc.RetryInfo" => {
+                    retry_delay = detail["retryDelay"].as_str().and_then(parse_duration);
+                },
+                _ => {}
+            }
+        }
+        if !is_quota_error { return None }
+
+        Some(QuotaFailure {
+            message: error["message"].as_str().unwrap_or("").to_string(),
+            violations,
+            retry_delay,
+            request_id: None,
+        })
+    }
+}
+
+/// Parses protobuf JSON durations like `"27s"` or `"1.5s"`
+fn parse_duration(input: &str) -> Option<Duration> {
+    Duration::try_from_secs_f64(input.strip_suffix('s')?.parse().ok()?).ok()
+}
+
+/// Which of the [crate::SizeLimits] was exceeded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SizeLimit {
+    Request,
+    InlineData,
+    Response,
+} impl fmt::Display for SizeLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Request => "Request",
+            Self::InlineData => "Inline data",
+            Self::Response => "Response",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUOTA_ERROR: &str = r#"{
+        "error": {
+            "code": 429,
+            "message": "You exceeded your current quota, please check your plan and billing details.",
+            "status": "RESOURCE_EXHAUSTED",
+            "details": [
+                {
+                    "@type": "type.googleapis.com/google.rpc.QuotaFailure",
+                    "violations": [
+                        {
+                            "quotaMetric": "generativelanguage.googleapis.com/generate_content_free_tier_requests",
+                            "quotaId": "GenerateRequestsPerMinutePerProjectPerModel-FreeTier",
+                            "quotaDimensions": { "location": "global", "model": "gemini-2.0-flash" },
+                            "quotaValue": "15"
+                        },
+                        {
+                            "quotaMetric": "generativelanguage.googleapis.com/generate_content_free_tier_requests",
+                            "quotaId": "GenerateRequestsPerDayPerProjectPerModel-FreeTier",
+                            "quotaValue": "1500"
+                        }
+                    ]
+                },
+                {
+                    "@type": "type.googleapis.com/google.rpc.Help",
+                    "links": [{ "description": "Learn more about Gemini API quotas", "url": "https://ai.google.dev/gemini-api/docs/rate-limits" }]
+                },
+                {
+                    "@type": "type.googleapis.com/google.rpc.RetryInfo",
+                    "retryDelay": "27s"
+                }
+            ]
+        }
+    }"#;
+
+    const INVALID_KEY_ERROR: &str = r#"{
+        "error": {
+            "code": 400,
+            "message": "API key not valid. Please pass a valid API key.",
+            "status": "INVALID_ARGUMENT",
+            "details": [
+                {
+                    "@type": "type.googleapis.com/google.rpc.ErrorInfo",
+                    "reason": "API_KEY_INVALID",
+                    "domain": "googleapis.com",
+                    "metadata": { "service": "generativelanguage.googleapis.com" }
+                }
+            ]
+        }
+    }"#;
+
+    #[test]
+    fn parses_quota_failure() {
+        let json = json::parse(QUOTA_ERROR).unwrap();
+        let quota = QuotaFailure::get_fake(&json["error"]).unwrap();
+        assert!(quota.message.starts_with("You exceeded your current quota"));
+        assert_eq!(quota.retry_delay, Some(Duration::from_secs(27)));
+        assert_eq!(quota.violations.len(), 2);
+        assert_eq!(quota.violations[0].limit, Some(15));
+        assert_eq!(quota.violations[0].period(), QuotaPeriod::Minute);
+        assert_eq!(quota.violations[1].limit, Some(1500));
+        assert_eq!(quota.violations[1].period(), QuotaPeriod::Day);
+    }
+
+    #[test]
+    fn other_errors_are_not_quota_failures() {
+        let json = json::parse(INVALID_KEY_ERROR).unwrap();
+        assert!(QuotaFailure::get_fake(&json["error"]).is_none());
+        let error = ApiError::get_fake(&json["error"]);
+        assert_eq!(error.code, 400);
+        assert_eq!(error.status, Status::InvalidArgument);
+        assert_eq!(error.to_string(), "400 INVALID_ARGUMENT: API key not valid. Please pass a valid API key.");
+    }
+
+    #[test]
+    fn status_mappings() {
+        let all = [
+            Status::Ok, Status::Cancelled, Status::Unknown, Status::InvalidArgument, Status::DeadlineExceeded,
+            Status::NotFound, Status::AlreadyExists, Status::PermissionDenied, Status::Unauthenticated,
+            Status::ResourceExhausted, Status::FailedPrecondition, Status::Aborted, Status::OutOfRange,
+            Status::Unimplemented, Status::Internal, Status::Unavailable, Status::DataLoss,
+        ];
+        for status in all {
+            assert_eq!(Status::get_fake(status.get_real()), status);
+        }
+        assert_eq!(Status::get_fake("SOMETHING_NEW"), Status::Unknown);
+        assert_eq!(Status::from_http_code(429), Status::ResourceExhausted);
+        assert_eq!(Status::from_http_code(418), Status::Unknown);
+        // Falls back to the code when there is no status
+        let error = json::object! { "code": 503, "message": "The model is overloaded." };
+        assert_eq!(Status::from_error(&error), Status::Unavailable);
+        assert_eq!(ApiError::get_fake(&error).code, 503);
+        // And to the status's code when there is no code
+        assert_eq!(ApiError::get_fake(&json::object! { "status": "NOT_FOUND" }).code, 404);
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("27s"), Some(Duration::from_secs(27)));
+        assert_eq!(parse_duration("1.5s"), Some(Duration::from_millis(1500)));
+        assert_eq!(parse_duration("0s"), Some(Duration::ZERO));
+        for malformed in ["27", "-1s", "infs", "NaNs", "s", "abcs"] {
+            assert_eq!(parse_duration(malformed), None, "{malformed}");
+        }
+    }
+}
@@ -0,0 +1,219 @@
+//! Runs a set of prompts against one or more models and grades the answers, to
+//! catch regressions when prompts or models change
+//!
+//! ## Example:
+//! ```rust,no_run
+//! # use gemini_rs::eval::{EvalCase, EvalSuite, Grader};
+//! # async fn run() {
+//! let api_key = std::env::var("GEMINI_API_KEY").unwrap();
+//! let report = EvalSuite::new(api_key)
+//!     .model("gemini-1.5-flash")
+//!     .model("gemini-1.5-pro")
+//!     .case(EvalCase::new("capital", "What is the capital of France? One word.", Grader::ExactMatch("Paris".to_string())))
+//!     .case(EvalCase::new("json", "Give me a JSON object with a \"name\" key", Grader::Json { required_keys: vec!["name".to_string()] }))
+//!     .case(EvalCase::new("tone", "Say hi to a customer", Grader::Judge {
+//!         model: "gemini-1.5-pro".to_string(),
+//!         criteria: "The reply is polite and under 20 words".to_string(),
+//!     }))
+//!     .run()
+//!     .await;
+//! println!("{report}");
+//! # }
+//! ```
+use std::{fmt, sync::Arc};
+
+use crate::{output::{JsonOutput, OutputParser}, pool::ConversationPool, Conversation, Part};
+
+/// Decides whether an answer passes
+#[derive(Clone)]
+pub enum Grader {
+    /// The answer equals this text, ignoring case and surrounding whitespace
+    ExactMatch(String),
+    /// The answer contains this text, ignoring case
+    Contains(String),
+    /// The answer contains a JSON object with all of these keys
+    Json { required_keys: Vec<String> },
+    /// Another model decides if the answer meets the criteria
+    Judge { model: String, criteria: String },
+    /// Any check, e.g. a regex from the `regex` crate
+    Custom(Arc<dyn Fn(&str) -> bool + Send + Sync>),
+} impl fmt::Debug for Grader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ExactMatch(text) => f.debug_tuple("ExactMatch").field(text).finish(),
+            Self::Contains(text) => f.debug_tuple("Contains").field(text).finish(),
+            Self::Json { required_keys } => f.debug_struct("Json").field("required_keys", required_keys).finish(),
+            Self::Judge { model, criteria } => f.debug_struct("Judge")
+                .field("model", model)
+                .field("criteria", criteria)
+                .finish(),
+            Self::Custom(_) => f.write_str("Custom"),
+        }
+    }
+} impl Grader {
+    /// Returns why the answer failed, or `None` if it passed
+    async fn grade(&self, token: &str, prompt: &str, answer: &str) -> Option<String> {
+        match self {
+            Self::ExactMatch(expected) => (!answer.trim().eq_ignore_ascii_case(expected.trim()))
+                .then(|| format!("Expected \"{expected}\"")),
+            Self::Contains(expected) => (!answer.to_lowercase().contains(&expected.to_lowercase()))
+                .then(|| format!("Expected the answer to contain \"{expected}\"")),
+            Self::Json { required_keys } => match JsonOutput::default().parse(answer) {
+                Ok(json) if json.is_object() => required_keys.iter()
+                    .find(|key| !json.has_key(key))
+                    .map(|key| format!("Missing the \"{key}\" key")),
+                Ok(_) => Some("Expected a JSON object".to_string()),
+                Err(e) => Some(e.to_string()),
+            },
+            Self::Judge { model, criteria } => {
+                let mut convo = Conversation::new(token.to_string(), model.clone());
+                let verdict = convo.generate_content(vec![Part::Text(format!(
+                    "You are grading an answer. Reply with PASS or FAIL, then a short reason.\n\
+                    Criteria: {criteria}\n\nPrompt: {prompt}\n\nAnswer: {answer}"
+                ))]).await;
+                match verdict {
+                    Ok(verdict) if verdict.text().trim_start().to_uppercase().starts_with("PASS") => None,
+                    Ok(verdict) => Some(format!("Judge: {0}", verdict.text().trim())),
+                    Err(e) => Some(format!("Judge failed: {0}", e.to_string_without_url())),
+                }
+            },
+            Self::Custom(check) => (!check(answer)).then(|| "Custom check failed".to_string()),
+        }
+    }
+}
+
+/// A prompt and how to grade its answer
+#[derive(Debug, Clone)]
+pub struct EvalCase {
+    pub name: String,
+    pub prompt: String,
+    pub grader: Grader,
+} impl EvalCase {
+    pub fn new(name: &str, prompt: &str, grader: Grader) -> Self {
+        Self {
+            name: name.to_string(),
+            prompt: prompt.to_string(),
+            grader,
+        }
+    }
+}
+
+/// Cases and the models to run them against, see the [module docs](self)
+pub struct EvalSuite {
+    token: String,
+    models: Vec<String>,
+    cases: Vec<EvalCase>,
+    concurrency: usize,
+} impl fmt::Debug for EvalSuite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("EvalSuite")
+            .field("token", &"[REDACTED]")
+            .field("models", &self.models)
+            .field("cases", &self.cases)
+            .field("concurrency", &self.concurrency)
+            .finish()
+    }
+} impl EvalSuite {
+    pub fn new(token: String) -> Self {
+        Self {
+            token,
+            models: vec![],
+            cases: vec![],
+            concurrency: 4,
+        }
+    }
+
+    pub fn model(mut self, model: &str) -> Self {
+        self.models.push(model.to_string());
+        self
+    }
+
+    pub fn case(mut self, case: EvalCase) -> Self {
+        self.cases.push(case);
+        self
+    }
+
+    /// How many prompts run at the same time for each model, 4 by default
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.concurrency = concurrency;
+        self
+    }
+
+    /// Runs every case against every model. Failed requests count as failed cases.
+    pub async fn run(&self) -> EvalReport {
+        let mut pending = vec![];
+        for model in &self.models {
+            let pool = ConversationPool::new(self.token.clone(), model.clone(), self.concurrency);
+            for case in &self.cases {
+                pending.push((model, case, pool.submit(vec![Part::Text(case.prompt.clone())])));
+            }
+        }
+
+        let mut results = vec![];
+        for (model, case, handle) in pending {
+            let (output, failure) = match handle.await {
+                Ok(Ok(response)) => {
+                    let output = response.text();
+                    let failure = case.grader.grade(&self.token, &case.prompt, &output).await;
+                    (output, failure)
+                },
+                Ok(Err(e)) => (String::new(), Some(format!("Request failed: {0}", e.to_string_without_url()))),
+                Err(e) => (String::new(), Some(format!("Request panicked: {e}"))),
+            };
+            results.push(EvalResult {
+                model: model.clone(),
+                case: case.name.clone(),
+                passed: failure.is_none(),
+                output,
+                failure,
+            });
+        }
+        EvalReport { results }
+    }
+}
+
+/// The outcome of one case on one model
+#[derive(Debug, Clone)]
+pub struct EvalResult {
+    pub model: String,
+    pub case: String,
+    pub passed: bool,
+    pub output: String,
+    /// Why the case failed
+    pub failure: Option<String>,
+}
+
+/// Every result of [EvalSuite::run]. Displays as a summary per model followed by the failures.
+#[derive(Debug, Clone)]
+pub struct EvalReport {
+    pub results: Vec<EvalResult>,
+} impl EvalReport {
+    /// The fraction of cases `model` passed, from 0 to 1
+    pub fn pass_rate(&self, model: &str) -> f64 {
+        let results: Vec<_> = self.results.iter().filter(|i| i.model == model).collect();
+        if results.is_empty() { return 0.0 }
+        results.iter().filter(|i| i.passed).count() as f64 / results.len() as f64
+    }
+
+    pub fn failures(&self) -> impl Iterator<Item = &EvalResult> {
+        self.results.iter().filter(|i| !i.passed)
+    }
+}
+
+impl fmt::Display for EvalReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut models: Vec<&str> = vec![];
+        for i in &self.results {
+            if !models.contains(&i.model.as_str()) { models.push(&i.model) }
+        }
+        for model in models {
+            let total = self.results.iter().filter(|i| i.model == model).count();
+            let passed = self.results.iter().filter(|i| i.model == model && i.passed).count();
+            writeln!(f, "{model}: {passed}/{total} passed ({0:.0}%)", self.pass_rate(model) * 100.0)?;
+        }
+        for i in self.failures() {
+            writeln!(f, "FAIL {0} on {1}: {2}", i.case, i.model, i.failure.as_deref().unwrap_or(""))?;
+        }
+        Ok(())
+    }
+}
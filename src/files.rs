@@ -1,7 +1,16 @@
 //! Handles everything related to prompting Gemini with external files.
+use std::{fmt, io::{Read, Seek, SeekFrom}, path::Path, sync::Arc, time::{Duration, Instant}};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
 use reqwest::Method;
 
-use crate::GeminiError;
+use crate::{response::GeminiResponse, Conversation, GeminiError, Part};
+
+/// Files at or under this size are sent inline by [attach_file] by default.
+///
+/// Requests are limited to 20MB and base64 makes files about a third bigger,
+/// so this leaves some room for the rest of the prompt.
+pub const DEFAULT_INLINE_LIMIT: u64 = 14 * 1024 * 1024;
 
 /// Stores a file used for prompting Gemini
 #[derive(Debug, Clone)]
@@ -15,6 +24,161 @@ pub struct GeminiFile {
             mime_type: "".to_string()
         }
     }
+
+    /// Waits for an uploaded file to finish processing, checking every 2 seconds.
+    ///
+    /// Prompts referencing a file that is still `PROCESSING` (common with videos
+    /// and PDFs) fail, so call this before using a freshly uploaded file.
+    pub async fn wait_until_active(&self, api_key: &str, timeout: Duration) -> Result<(), GeminiError<'static>> {
+        let deadline = Instant::now() + timeout;
+        let client = reqwest::Client::new();
+        loop {
+            let response = client
+                .request(Method::GET, format!(
+                    "https://generativelanguage.googleapis.com/v1beta/{0}",
+                    self.name()
+                ))
+                .header(crate::API_KEY_HEADER, api_key)
+                .send()
+                .await?
+                .text()
+                .await?;
+            let file_json = json::parse(&response)?;
+            crate::check_api_error(&file_json)?;
+            match file_json["state"].as_str() {
+                Some("PROCESSING") => {},
+                Some("FAILED") => return Err(GeminiError::FileError(format!("Processing {0} failed", self.name()))),
+                _ => return Ok(())
+            }
+            if Instant::now() >= deadline {
+                return Err(GeminiError::FileError(format!(
+                    "{0} is still processing after {1:?}", self.name(), timeout
+                )))
+            }
+            tokio::time::sleep(Duration::from_secs(2).min(deadline.saturating_duration_since(Instant::now()))).await;
+        }
+    }
+
+    /// The resource name used by the Files API, e.g. `files/abc123`
+    pub fn name(&self) -> &str {
+        match self.file_uri.find("files/") {
+            Some(i) => &self.file_uri[i..],
+            None => &self.file_uri
+        }
+    }
+}
+
+/// A file sent directly inside the request, base64 encoded
+#[derive(Clone)]
+pub struct InlineData {
+    pub mime_type: String,
+    /// The file's bytes as base64, shared so cloning history doesn't copy the file
+    pub data: Arc<str>
+} impl fmt::Debug for InlineData {
+    /// Shows the length of the data instead of the data, which can be megabytes
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("InlineData")
+            .field("mime_type", &self.mime_type)
+            .field("data", &format_args!("<{0} bytes of base64>", self.data.len()))
+            .finish()
+    }
+} impl InlineData {
+    pub fn decode(&self) -> Result<Vec<u8>, base64::DecodeError> {
+        STANDARD.decode(self.data.as_bytes())
+    }
+}
+
+/// Guesses the mime type of a file from its extension, for the types Gemini accepts.
+/// Returns `None` for anything else, rather than guessing `text/plain`.
+pub fn mime_type_for_path(path: impl AsRef<Path>) -> Option<&'static str> {
+    let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+    Some(match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "webp" => "image/webp",
+        "heic" => "image/heic",
+        "pdf" => "application/pdf",
+        "mp3" => "audio/mpeg",
+        "wav" => "audio/wav",
+        "ogg" => "audio/ogg",
+        "mp4" => "video/mp4",
+        "mov" => "video/quicktime",
+        "txt" => "text/plain",
+        "html" => "text/html",
+        "csv" => "text/csv",
+        "md" => "text/markdown",
+        "json" => "application/json",
+        _ => return None
+    })
+}
+
+/// Picks a file extension for a mime type, falling back to `bin`
+pub fn mime_extension(mime_type: &str) -> &str {
+    let mime_type = mime_type.split(';').next().unwrap_or("").trim();
+    match mime_type {
+        "image/jpeg" => "jpg",
+        "image/svg+xml" => "svg",
+        "audio/mpeg" => "mp3",
+        "audio/L16" | "audio/pcm" => "pcm",
+        "text/plain" => "txt",
+        "text/markdown" => "md",
+        "application/octet-stream" | "" => "bin",
+        _ => match mime_type.split_once('/') {
+            Some((_, subtype)) if subtype.chars().all(|c| c.is_ascii_alphanumeric()) => subtype,
+            _ => "bin"
+        }
+    }
+}
+
+/// Reads a file into a [Part::InlineData] without uploading it
+pub fn inline_file<'a>(path: &str, mime_type: &str) -> Result<Part, GeminiError<'a>> {
+    let bytes = std::fs::read(path)?;
+    Ok(Part::InlineData(InlineData {
+        mime_type: mime_type.to_string(),
+        data: STANDARD.encode(bytes).into()
+    }))
+}
+
+/// Attaches a local file, sending it inline if it is at most `inline_limit` bytes
+/// (see [DEFAULT_INLINE_LIMIT]) and uploading it with [upload_file] otherwise
+pub async fn attach_file<'a>(path: &'a str, mime_type: &'a str, api_key: &'a str, inline_limit: u64) ->
+        Result<Part, GeminiError<'a>> {
+
+    if std::fs::metadata(path)?.len() <= inline_limit {
+        return inline_file(path, mime_type)
+    }
+    Ok(Part::File(upload_file(path, mime_type, api_key).await?))
+}
+
+/// Images with a side longer than this get scaled down by [Part::from_image]
+#[cfg(feature = "image")]
+pub const MAX_IMAGE_SIDE: u32 = 3072;
+
+#[cfg(feature = "image")]
+impl Part {
+    /// Encodes an image as inline data (requires the `image` feature).
+    ///
+    /// Images with a side over [MAX_IMAGE_SIDE] are scaled down, and then halved
+    /// until they fit under [DEFAULT_INLINE_LIMIT].
+    pub fn from_image(image: &image::DynamicImage, format: image::ImageFormat) -> Result<Part, GeminiError<'static>> {
+        let mut image = if image.width().max(image.height()) > MAX_IMAGE_SIDE {
+            image.resize(MAX_IMAGE_SIDE, MAX_IMAGE_SIDE, image::imageops::FilterType::Triangle)
+        } else {
+            image.clone()
+        };
+        loop {
+            let mut bytes = std::io::Cursor::new(vec![]);
+            image.write_to(&mut bytes, format)?;
+            let bytes = bytes.into_inner();
+            if bytes.len() as u64 <= DEFAULT_INLINE_LIMIT || image.width().max(image.height()) <= 1 {
+                return Ok(Part::InlineData(InlineData {
+                    mime_type: format.to_mime_type().to_string(),
+                    data: STANDARD.encode(bytes).into()
+                }))
+            }
+            image = image.resize(image.width() / 2, image.height() / 2, image::imageops::FilterType::Triangle);
+        }
+    }
 }
 
 /// Uploads an file to the Google API
@@ -22,73 +186,269 @@ pub struct GeminiFile {
 /// Use a mime filetype from <https://www.iana.org/assignments/media-types/media-types.xhtml>,
 /// unfortunately this is neccassary for Google to properly proccess the file.
 /// ## Example:
-/// ```rust
-/// let api_key = env::var("GEMINI_API_KEY").unwrap(); 
+/// ```rust,no_run
+/// # use std::env;
+/// # use gemini_rs::{Conversation, Part, files::upload_file};
+/// # async fn run() {
+/// let api_key = env::var("GEMINI_API_KEY").unwrap();
 /// let mut convo = Conversation::new(
 ///     api_key.clone(),
 ///     "gemini-1.5-flash".to_string()
 /// );
-/// let image = upload_image("Testing/cat.png", "image/png", &api_key).await.unwrap();
+/// let image = upload_file("Testing/cat.png", "image/png", &api_key).await.unwrap();
 /// let response = convo.generate_content(vec![
 ///     Part::Text("Describe this scene".to_string()),
 ///     Part::File(image)
 /// ]).await.unwrap();
-/// println!("{0}", response.get_text());
+/// println!("{0}", response.text());
+/// # }
 /// ```
-pub async fn upload_file<'a>(image_path: &'a str, mime_type: &'a str, api_key: &'a str) -> 
-        Result<GeminiFile, GeminiError<'a>> {
+pub async fn upload_file(image_path: &str, mime_type: &str, api_key: &str) ->
+        Result<GeminiFile, GeminiError<'static>> {
 
     let file = std::fs::File::open(image_path)?;
-    let file_size = file.metadata().unwrap().len();
-    let url = format!(
-        "https://generativelanguage.googleapis.com/upload/v1beta/files?key={0}",
-        api_key
-    );
-    let file_name = image_path.split("/").last().unwrap().split_once(".").unwrap().0;
-    let data = r#"{"file": {"display_name": ""#.to_owned() + file_name + r#""}}"#;
-
+    let file_size = file.metadata()?.len();
     let client = reqwest::Client::new();
+    let (upload_url, _) = start_upload(&client, image_path, mime_type, api_key, file_size).await?;
+
+    // Upload the actual bytes
+    let bytes_request = client
+        .request(Method::POST, upload_url)
+        .header("Content-Length", file_size)
+        .header("X-Goog-Upload-Offset", 0)
+        .header("X-Goog-Upload-Command", "upload, finalize")
+        .body(std::fs::read(image_path)?)
+        .send()
+        .await?;
+
+    finish_upload(bytes_request, mime_type).await
+}
+
+/// A reasonable chunk size for [upload_file_chunked]
+pub const DEFAULT_CHUNK_SIZE: u64 = 8 * 1024 * 1024;
+
+/// Uploads a file in chunks, for big files (like long videos) over unreliable connections.
+///
+/// `chunk_size` is rounded up to what Google accepts. A chunk that fails to send
+/// is retried up to 3 times, continuing from the last byte Google received.
+/// `progress` is called with the bytes uploaded so far and the total after every chunk.
+/// ## Example:
+/// ```rust,no_run
+/// # use gemini_rs::files::{upload_file_chunked, DEFAULT_CHUNK_SIZE};
+/// # async fn run() {
+/// let api_key = std::env::var("GEMINI_API_KEY").unwrap();
+/// let video = upload_file_chunked("video.mp4", "video/mp4", &api_key, DEFAULT_CHUNK_SIZE, |sent, total| {
+///     println!("{sent}/{total} bytes uploaded");
+/// }).await.unwrap();
+/// # }
+/// ```
+pub async fn upload_file_chunked(
+    path: &str,
+    mime_type: &str,
+    api_key: &str,
+    chunk_size: u64,
+    progress: impl Fn(u64, u64)
+) -> Result<GeminiFile, GeminiError<'static>> {
+    upload_file_chunked_with_client(&reqwest::Client::new(), path, mime_type, api_key, chunk_size, progress).await
+}
+
+/// Same as [upload_file_chunked], but sends the requests with `client`, e.g. one with
+/// a proxy or longer timeouts for big files
+pub async fn upload_file_chunked_with_client(
+    client: &reqwest::Client,
+    path: &str,
+    mime_type: &str,
+    api_key: &str,
+    chunk_size: u64,
+    progress: impl Fn(u64, u64)
+) -> Result<GeminiFile, GeminiError<'static>> {
+    let mut file = std::fs::File::open(path)?;
+    let file_size = file.metadata()?.len();
+    let (upload_url, granularity) = start_upload(client, path, mime_type, api_key, file_size).await?;
+    let chunk_size = chunk_size.max(1).div_ceil(granularity) * granularity;
+
+    let mut offset = 0;
+    let mut failures = 0;
+    loop {
+        let length = chunk_size.min(file_size - offset);
+        let last = offset + length == file_size;
+        let mut chunk = vec![0; length as usize];
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut chunk)?;
+
+        let sent = client
+            .request(Method::POST, &upload_url)
+            .header("Content-Length", length)
+            .header("X-Goog-Upload-Offset", offset)
+            .header("X-Goog-Upload-Command", if last { "upload, finalize" } else { "upload" })
+            .body(chunk)
+            .send()
+            .await
+            .and_then(|i| i.error_for_status());
+
+        match sent {
+            Ok(response) if last => {
+                progress(file_size, file_size);
+                return finish_upload(response, mime_type).await
+            },
+            Ok(_) => {
+                offset += length;
+                failures = 0;
+            },
+            Err(e) => {
+                failures += 1;
+                if failures > 3 { return Err(e.into()) }
+                // Ask Google how much actually arrived and carry on from there
+                let status = client
+                    .request(Method::POST, &upload_url)
+                    .header("X-Goog-Upload-Command", "query")
+                    .send()
+                    .await?;
+                let received = status.headers()
+                    .get("x-goog-upload-size-received")
+                    .and_then(|i| i.to_str().ok())
+                    .and_then(|i| i.parse().ok())
+                    .unwrap_or(offset);
+                if received > file_size {
+                    return Err(GeminiError::FileError(format!(
+                        "Google reports {received} bytes received, but {path} is only {file_size} bytes"
+                    )))
+                }
+                offset = received;
+            }
+        }
+        progress(offset, file_size);
+    }
+}
+
+/// Sends the file's metadata and returns the URL to upload its bytes to, along with
+/// the size chunks have to be a multiple of
+async fn start_upload(
+    client: &reqwest::Client,
+    path: &str,
+    mime_type: &str,
+    api_key: &str,
+    file_size: u64
+) -> Result<(String, u64), GeminiError<'static>> {
+    let url = "https://generativelanguage.googleapis.com/upload/v1beta/files";
+    let file_name = path.split("/").last().unwrap_or(path);
+    let file_name = file_name.split_once(".").map(|i| i.0).unwrap_or(file_name);
+    let data = json::object! {
+        "file": { "display_name": file_name }
+    };
 
     // Upload metadata to google servers
     let metadata_request = client
-        .request(Method::POST, &url)
+        .request(Method::POST, url)
+        .header(crate::API_KEY_HEADER, api_key)
         .header("X-Goog-Upload-Protocol", "resumable")
         .header("X-Goog-Upload-Command", "start")
         .header("X-Goog-Upload-Header-Content-Length", file_size)
         .header("X-Goog-Upload-Header-Content-Type", mime_type)
         .header("Content-Type", "application/json")
-        .body(data)
+        .body(data.dump())
         .send()
-        .await
-        .unwrap();
+        .await?;
 
     let metadata_req_headers = metadata_request.headers();
-    let upload_url = metadata_req_headers.get("x-goog-upload-url").unwrap().to_str().unwrap();
+    let header = |name: &str| metadata_req_headers.get(name).and_then(|i| i.to_str().ok());
+    let upload_url = header("x-goog-upload-url")
+        .ok_or(GeminiError::ParseError("Failed to get the upload url"))?
+        .to_string();
+    let granularity = header("x-goog-upload-chunk-granularity")
+        .and_then(|i| i.parse().ok())
+        .filter(|i| *i > 0)
+        .unwrap_or(256 * 1024);
+    Ok((upload_url, granularity))
+}
 
-    // Upload the actual bytes
-    let _bytes_request = client
-        .request(Method::POST, upload_url)
-        .header("Content-Length", file_size)
-        .header("X-Goog-Upload-Offset", 0)
-        .header("X-Goog-Upload-Command", "upload, finalize")
-        .body(std::fs::read(image_path).unwrap())
-        .send()
-        .await;
+/// Reads the uploaded file out of the response to the finalizing request
+async fn finish_upload(response: reqwest::Response, mime_type: &str) -> Result<GeminiFile, GeminiError<'static>> {
+    let uploaded = json::parse(&response.text().await?)?;
+    crate::check_api_error(&uploaded)?;
+
+    Ok(GeminiFile{
+        file_uri: uploaded["file"]["uri"]
+            .as_str()
+            .ok_or(GeminiError::ParseError("Failed to extract file uri"))?
+            .to_string(),
+        mime_type: mime_type.to_string()
+    })
+}
 
-    // TEST
-    let file_list_request = client
-        .request(Method::GET, format!(
-            "https://generativelanguage.googleapis.com/v1beta/files/?key={0}",
-            std::env::var("GEMINI_API_KEY").unwrap()
+/// Deletes a file uploaded with [upload_file]
+pub async fn delete_file(file: &GeminiFile, api_key: &str) -> Result<(), GeminiError<'static>> {
+    let response = reqwest::Client::new()
+        .request(Method::DELETE, format!(
+            "https://generativelanguage.googleapis.com/v1beta/{0}",
+            file.name()
         ))
+        .header(crate::API_KEY_HEADER, api_key)
         .send()
-        .await
-        .unwrap();
+        .await?
+        .text()
+        .await?;
+    if !response.trim().is_empty() {
+        crate::check_api_error(&json::parse(&response)?)?;
+    }
+    Ok(())
+}
+
+/// Asks a single question about local files.
+///
+/// The files (a list of path and mime type) are uploaded with [upload_file], and
+/// once they are done processing they get sent along with `question` to `model`.
+/// If `delete_after` is true, they are deleted again after the response arrives.
+/// ## Example:
+/// ```rust,no_run
+/// # use gemini_rs::files::ask_files;
+/// # async fn run() {
+/// let api_key = std::env::var("GEMINI_API_KEY").unwrap();
+/// let response = ask_files(
+///     &api_key,
+///     "gemini-1.5-flash",
+///     &[("a.pdf", "application/pdf"), ("b.pdf", "application/pdf")],
+///     "What are the differences between these documents?",
+///     true
+/// ).await.unwrap();
+/// println!("{0}", response.text());
+/// # }
+/// ```
+pub async fn ask_files(
+    api_key: &str,
+    model: &str,
+    files: &[(&str, &str)],
+    question: &str,
+    delete_after: bool
+) -> Result<GeminiResponse, GeminiError<'static>> {
+    let mut uploaded = vec![];
+    let mut result = Ok(());
+    for (path, mime_type) in files {
+        match upload_file(path, mime_type, api_key).await {
+            Ok(file) => uploaded.push(file),
+            Err(e) => { result = Err(e); break }
+        }
+    }
+    for file in &uploaded {
+        if result.is_err() { break }
+        result = file.wait_until_active(api_key, Duration::from_secs(120)).await;
+    }
 
-    let files_list = &json::parse(&file_list_request.text().await.unwrap()).unwrap()["files"];
+    let response = match result {
+        Ok(()) => {
+            let mut parts: Vec<Part> = uploaded.iter().cloned().map(Part::File).collect();
+            parts.push(Part::Text(question.to_string()));
+            Conversation::new(api_key.to_string(), model.to_string())
+                .generate_content(parts)
+                .await
+        },
+        Err(e) => Err(e)
+    };
 
-    Ok(GeminiFile{
-        file_uri: files_list[0]["uri"].as_str().unwrap().to_string(),
-        mime_type: mime_type.to_string()
-    })
+    if delete_after {
+        for file in &uploaded {
+            let _ = delete_file(file, api_key).await;
+        }
+    }
+    response
 }
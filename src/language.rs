@@ -0,0 +1,68 @@
+//! Rough language detection, used to check replies from a conversation pinned to a
+//! language with [crate::Conversation::respond_in]
+//!
+//! Detection counts common words, so it only knows a few languages written in the
+//! Latin alphabet and needs a couple of sentences to be reliable.
+//! ## Example:
+//! ```rust
+//! # use gemini_rs::language::detect_language;
+//! assert_eq!(detect_language("Das ist nicht so schlimm, und wir sind bald da."), Some("de"));
+//! assert_eq!(detect_language("Hi"), None);
+//! ```
+
+/// Languages [detect_language] can recognize, with their most common words
+const COMMON_WORDS: &[(&str, &[&str])] = &[
+    ("en", &["the", "and", "is", "are", "of", "to", "in", "that", "it", "you", "with", "for", "this", "was", "not", "be", "have", "on"]),
+    ("de", &["der", "die", "das", "und", "ist", "nicht", "ich", "sie", "es", "zu", "mit", "ein", "eine", "den", "auf", "wir", "sind", "auch"]),
+    ("fr", &["le", "la", "les", "et", "est", "un", "une", "des", "que", "pas", "je", "vous", "il", "dans", "pour", "sur", "avec", "du"]),
+    ("es", &["el", "la", "los", "las", "y", "es", "que", "de", "un", "una", "no", "en", "por", "con", "para", "está", "son", "del"]),
+    ("it", &["il", "lo", "la", "gli", "le", "e", "è", "che", "di", "un", "una", "non", "per", "con", "sono", "del", "della", "questo"]),
+    ("pt", &["o", "os", "as", "e", "é", "que", "de", "um", "uma", "não", "em", "por", "com", "para", "do", "da", "são", "isso"]),
+    ("nl", &["de", "het", "een", "en", "is", "niet", "ik", "je", "van", "dat", "met", "op", "zijn", "voor", "we", "ook", "er", "maar"]),
+];
+
+/// The language code of `text` if it is one of the few languages this knows and
+/// there is enough text to tell, otherwise `None`
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let words: Vec<String> = text
+        .split(|c: char| !c.is_alphabetic())
+        .filter(|i| !i.is_empty())
+        .map(str::to_lowercase)
+        .collect();
+    if words.len() < 5 { return None }
+
+    let mut scores: Vec<(usize, &str)> = COMMON_WORDS.iter()
+        .map(|(code, common)| (words.iter().filter(|i| common.contains(&i.as_str())).count(), *code))
+        .collect();
+    scores.sort_by_key(|i| std::cmp::Reverse(i.0));
+    let (best, code) = scores[0];
+    // Short common words are shared between languages, so require a clear winner
+    (best >= 3 && best > scores[1].0 * 3 / 2).then_some(code)
+}
+
+/// The English name of a language code, falling back to the code itself
+pub fn language_name(code: &str) -> &str {
+    match primary_subtag(code).as_str() {
+        "en" => "English",
+        "de" => "German",
+        "fr" => "French",
+        "es" => "Spanish",
+        "it" => "Italian",
+        "pt" => "Portuguese",
+        "nl" => "Dutch",
+        "ja" => "Japanese",
+        "zh" => "Chinese",
+        "ko" => "Korean",
+        "ru" => "Russian",
+        "pl" => "Polish",
+        "tr" => "Turkish",
+        "ar" => "Arabic",
+        "hi" => "Hindi",
+        _ => code,
+    }
+}
+
+/// `de` for `de-AT` or `DE_at`
+pub(crate) fn primary_subtag(code: &str) -> String {
+    code.split(['-', '_']).next().unwrap_or("").to_lowercase()
+}
@@ -4,9 +4,36 @@ pub mod safety;
 pub mod response;
 pub mod files;
 pub mod saving;
+pub mod router;
+pub mod context;
+pub mod error;
+pub mod openai;
+pub mod output;
+pub mod tree;
+pub mod pool;
+pub mod guard;
+pub mod redact;
+pub mod editing;
+pub mod models;
+pub mod caching;
+pub mod eval;
+pub mod replay;
+pub mod shared;
+pub mod actor;
+pub mod language;
+pub mod batch;
+pub mod memory;
+pub mod rag;
+pub mod text;
+pub mod moderation;
+pub mod credentials;
+pub mod repl;
+#[cfg(feature = "polling")]
+pub mod polling;
+mod telemetry;
 
-use std::io;
-use files::GeminiFile;
+use std::{collections::BTreeMap, fmt, future::Future, io, sync::{atomic::{AtomicU64, Ordering}, Arc}, time::{SystemTime, UNIX_EPOCH}};
+use files::{GeminiFile, InlineData};
 use json::JsonValue;
 use reqwest::{Client, Method};
 use thiserror::Error;
@@ -19,6 +46,11 @@ pub enum GeminiError<'a> {
     #[error("HTTP request failed: {0}")]
     RequestError(#[from] reqwest::Error),
     
+    /// Same as [GeminiError::RequestError], for a generateContent request, with the id
+    /// it was sent with
+    #[error("HTTP request {request_id} failed: {source}")]
+    HttpError { request_id: String, source: reqwest::Error },
+
     /// Error type for IO errors
     #[error("IO operation failed: {0}")]
     IoError(#[from] io::Error),
@@ -36,8 +68,120 @@ pub enum GeminiError<'a> {
 
     #[error("{0}")]
     KeyError(String),
+
+    /// Error type for errors returned by the API, such as an invalid key or request
+    #[error("API error: {0}")]
+    ApiError(#[from] error::ApiError),
+
+    /// Error type for files that failed or took too long to process
+    #[error("{0}")]
+    FileError(String),
+
+    /// Error type for inline data that isn't valid base64
+    #[error("Base64 decoding failed: {0}")]
+    Base64Error(#[from] base64::DecodeError),
+
+    /// Error type for encoding images passed to [Part::from_image]
+    #[cfg(feature = "image")]
+    #[error("Image encoding failed: {0}")]
+    ImageError(#[from] image::ImageError),
+
+    /// A setting was rejected before anything was sent
+    #[error("Invalid config: {0}")]
+    ConfigError(String),
+
+    /// A different model version answered than the one requested, see
+    /// [Conversation::set_strict_model_version]
+    #[error("Requested {requested}, but {served} answered")]
+    ModelVersionMismatch { requested: String, served: String },
+
+    /// The reply wasn't in the language set with [Conversation::respond_in]
+    #[error("Expected a reply in {expected}, got one in {detected}")]
+    LanguageMismatch { expected: String, detected: String },
+
+    /// A replayed conversation had no recorded response for a request
+    #[error("No recorded response: {0}")]
+    ReplayError(String),
+
+    /// Something was bigger than allowed by the conversation's [SizeLimits]
+    #[error("{limit} is {size} bytes, over the limit of {max} bytes")]
+    TooLarge { limit: error::SizeLimit, size: usize, max: usize },
+
+    /// A part of the prompt would be rejected by the API, see [Part::validate]
+    #[error("Invalid part: {0}")]
+    InvalidPart(String),
+
+    /// The prompt was blocked by the conversation's [guard::InputGuard]
+    #[error("Prompt was rejected: {0}")]
+    Rejected(String),
+
+    /// The request was cancelled before it finished, see [Conversation::generate_content_until]
+    #[error("Request was cancelled")]
+    Cancelled,
+
+    /// The deadline passed before a response came back
+    #[error("Deadline exceeded")]
+    DeadlineExceeded,
+
+    /// A quota or rate limit was hit, check the violations to see which one
+    #[error("Quota exhausted: {}{}", .0.message, .0.request_id.as_ref().map(|i| format!(" (request {i})")).unwrap_or_default())]
+    QuotaError(error::QuotaFailure),
+}
+
+impl GeminiError<'_> {
+    /// The error message with the query string taken out of any request URL in it,
+    /// since that is where the API key goes. Use this for messages that get saved
+    /// or shown to other people.
+    pub fn to_string_without_url(&self) -> String {
+        let message = self.to_string();
+        match self {
+            GeminiError::RequestError(e) | GeminiError::HttpError { source: e, .. } => match e.url() {
+                Some(url) if url.query().is_some() => {
+                    let mut stripped = url.clone();
+                    stripped.set_query(None);
+                    message.replace(url.as_str(), stripped.as_str())
+                },
+                _ => message
+            },
+            _ => message
+        }
+    }
+
+    /// The `X-Request-Id` of the generateContent request this error came from, for
+    /// API, quota and HTTP errors. Unlike [Conversation::last_request_id] it stays
+    /// right when the conversation is shared between tasks.
+    pub fn request_id(&self) -> Option<&str> {
+        match self {
+            GeminiError::HttpError { request_id, .. } => Some(request_id),
+            GeminiError::ApiError(e) => e.request_id.as_deref(),
+            GeminiError::QuotaError(e) => e.request_id.as_deref(),
+            _ => None
+        }
+    }
+
+    fn with_request_id(self, request_id: &str) -> Self {
+        match self {
+            GeminiError::RequestError(source) => GeminiError::HttpError { request_id: request_id.to_string(), source },
+            GeminiError::ApiError(mut e) => {
+                e.request_id = Some(request_id.to_string());
+                GeminiError::ApiError(e)
+            },
+            GeminiError::QuotaError(mut e) => {
+                e.request_id = Some(request_id.to_string());
+                GeminiError::QuotaError(e)
+            },
+            e => e
+        }
+    }
 }
 
+/// Header the API key is sent in, so it never ends up in URLs (and the error messages
+/// and logs that show them)
+pub(crate) const API_KEY_HEADER: &str = "x-goog-api-key";
+
+/// The most stop sequences the API accepts, see [Conversation::set_stop_sequences]
+pub const MAX_STOP_SEQUENCES: usize = 5;
+
 /// Represents a conversation with Gemini
 ///## Example usage:
 /// ```rs
@@ -49,35 +193,244 @@ pub enum GeminiError<'a> {
 ///let response = convo.prompt("Hello World!")await.unwrap();
 ///println!("{0:?}", a.text);
 /// ```
-#[derive(Debug)]
 pub struct Conversation {
     token: String,
     model: String,
     history: Vec<Message>,
     safety_settings: Vec<safety::SafetySetting>,
+    regeneration_policy: Option<response::RegenerationPolicy>,
+    last_request_id: Option<String>,
+    client: Client,
+    api_version: ApiVersion,
+    output: OutputKind,
+    input_guard: Option<Arc<dyn guard::InputGuard>>,
+    guard_failure_policy: guard::FailurePolicy,
+    redactor: Option<redact::Redactor>,
+    stop_sequences: Vec<String>,
+    metadata: BTreeMap<String, String>,
+    size_limits: SizeLimits,
+    cached_context: Option<caching::CachedContext>,
+    recording: Option<replay::Transcript>,
+    replay: Option<replay::Replayer>,
+    system_instruction: Option<String>,
+    language: Option<String>,
+    strict_model_version: bool,
+    memory: Option<Box<dyn memory::Memory>>,
+    memory_error: Option<GeminiError<'static>>,
+    generation_params: GenerationParams,
+} impl fmt::Debug for Conversation {
+    /// Same as a derived `Debug`, but the API key is redacted so conversations can be logged safely
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Conversation")
+            .field("token", &"[REDACTED]")
+            .field("model", &self.model)
+            .field("history", &self.history)
+            .field("safety_settings", &self.safety_settings)
+            .field("regeneration_policy", &self.regeneration_policy)
+            .field("last_request_id", &self.last_request_id)
+            .field("api_version", &self.api_version)
+            .field("output", &self.output)
+            .field("stop_sequences", &self.stop_sequences)
+            .field("metadata", &self.metadata)
+            .field("size_limits", &self.size_limits)
+            .field("cached_context", &self.cached_context_name())
+            .field("recording", &self.recording.is_some())
+            .field("replaying", &self.is_replaying())
+            .field("system_instruction", &self.system_instruction)
+            .field("language", &self.language)
+            .field("strict_model_version", &self.strict_model_version)
+            .field("memory", &self.memory.is_some())
+            .field("memory_error", &self.memory_error)
+            .field("generation_params", &self.generation_params)
+            .field("input_guard", &self.input_guard.is_some())
+            .field("guard_failure_policy", &self.guard_failure_policy)
+            .field("redactor", &self.redactor.as_ref().map(|_| "[REDACTED]"))
+            .finish()
+    }
+}
+
+/// Version of the Gemini API to send requests to
+///
+/// Some features are only available on [ApiVersion::V1Alpha], while
+/// [ApiVersion::V1] only has stable features.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ApiVersion {
+    V1,
+    #[default]
+    V1Beta,
+    V1Alpha,
+} impl ApiVersion {
+    pub fn get_real(&self) -> &str {
+        match self {
+            Self::V1 => "v1",
+            Self::V1Beta => "v1beta",
+            Self::V1Alpha => "v1alpha",
+        }
+    }
+}
+
+/// What kind of output Gemini should respond with, set with [Conversation::output]
+#[derive(Debug, Clone, Default)]
+pub enum OutputKind {
+    /// Plain text (the default)
+    #[default]
+    Text,
+    /// JSON, optionally following an OpenAPI style response schema
+    Json(Option<JsonValue>),
+    /// Text and images, for models that can generate images
+    Image,
+    /// Spoken audio using a prebuilt voice (e.g. `Kore`), for text-to-speech models
+    Audio(String),
+} impl OutputKind {
+    /// The `generationConfig` fields for this output, `None` for [OutputKind::Text]
+    pub fn get_real(&self) -> Option<JsonValue> {
+        match self {
+            Self::Text => None,
+            Self::Json(schema) => {
+                let mut config = json::object! { "responseMimeType": "application/json" };
+                if let Some(schema) = schema {
+                    config["responseSchema"] = schema.clone();
+                }
+                Some(config)
+            },
+            Self::Image => Some(json::object! { "responseModalities": ["TEXT", "IMAGE"] }),
+            Self::Audio(voice) => Some(json::object! {
+                "responseModalities": ["AUDIO"],
+                "speechConfig": {
+                    "voiceConfig": {
+                        "prebuiltVoiceConfig": { "voiceName": voice.as_str() }
+                    }
+                }
+            }),
+        }
+    }
+}
+
+/// Client-side size limits, checked before anything is sent so oversized prompts
+/// fail fast with [GeminiError::TooLarge]. `None` means no limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SizeLimits {
+    /// Size of the JSON request body, by default the API's limit of 20MB
+    pub max_request_bytes: Option<usize>,
+    /// Decoded size of a single inline data part
+    pub max_inline_bytes: Option<usize>,
+    /// Size of the response body
+    pub max_response_bytes: Option<usize>,
+} impl Default for SizeLimits {
+    fn default() -> Self {
+        Self {
+            max_request_bytes: Some(20 * 1024 * 1024),
+            max_inline_bytes: None,
+            max_response_bytes: None,
+        }
+    }
+} impl SizeLimits {
+    /// No limits at all
+    pub fn none() -> Self {
+        Self { max_request_bytes: None, max_inline_bytes: None, max_response_bytes: None }
+    }
+
+    fn check(limit: error::SizeLimit, size: usize, max: Option<usize>) -> Result<(), GeminiError<'static>> {
+        match max {
+            Some(max) if size > max => Err(GeminiError::TooLarge { limit, size, max }),
+            _ => Ok(())
+        }
+    }
+}
+
+/// Sampling settings for generation, sent in `generationConfig`. `None` leaves a
+/// field to the model's default, or to [models::ModelDefaults] if applied.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct GenerationParams {
+    pub temperature: Option<f64>,
+    pub top_p: Option<f64>,
+    pub top_k: Option<u64>,
+    pub max_output_tokens: Option<u64>,
+} impl GenerationParams {
+    /// Fills the fields that are `None` from `defaults`
+    pub fn or(self, defaults: GenerationParams) -> GenerationParams {
+        GenerationParams {
+            temperature: self.temperature.or(defaults.temperature),
+            top_p: self.top_p.or(defaults.top_p),
+            top_k: self.top_k.or(defaults.top_k),
+            max_output_tokens: self.max_output_tokens.or(defaults.max_output_tokens),
+        }
+    }
+
+    /// The `generationConfig` fields that are set
+    pub fn get_real(&self) -> JsonValue {
+        let mut config = json::object! {};
+        if let Some(i) = self.temperature { config["temperature"] = i.into() }
+        if let Some(i) = self.top_p { config["topP"] = i.into() }
+        if let Some(i) = self.top_k { config["topK"] = i.into() }
+        if let Some(i) = self.max_output_tokens { config["maxOutputTokens"] = i.into() }
+        config
+    }
+
+    /// Reads the fields of a `generationConfig`, or the defaults listed for a model
+    /// by the models endpoint (where the token limit is `outputTokenLimit`)
+    pub fn get_fake(input: &JsonValue) -> GenerationParams {
+        GenerationParams {
+            temperature: input["temperature"].as_f64(),
+            top_p: input["topP"].as_f64(),
+            top_k: input["topK"].as_u64(),
+            max_output_tokens: input["maxOutputTokens"].as_u64().or(input["outputTokenLimit"].as_u64()),
+        }
+    }
+}
+
+/// A request that hasn't been sent, from [Conversation::request_json]
+#[derive(Debug, Clone)]
+pub struct RequestPreview {
+    /// The endpoint. The API key isn't part of it, it goes in the `x-goog-api-key` header.
+    pub url: String,
+    pub body: JsonValue,
 }
 
 /// A part of a conversation, used to store history
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Message {
     pub content: Vec<Part>,
     pub role: String
 } impl Message {
+    /// All text parts joined together, skipping files and inline data
+    pub fn text(&self) -> String {
+        self.content.iter().filter_map(|part| match part {
+            Part::Text(text) => Some(text.as_str()),
+            _ => None
+        }).collect()
+    }
+
+    /// Like [Message::text], but avoids copying when there is a single text part
+    pub fn into_text(self) -> String {
+        let mut texts: Vec<String> = self.content.into_iter().filter_map(|part| match part {
+            Part::Text(text) => Some(text),
+            _ => None
+        }).collect();
+        if texts.len() == 1 { texts.remove(0) } else { texts.concat() }
+    }
+
     pub fn get_real(&self) -> JsonValue {
         let mut obj = json::object! {
             "parts": [],
             "role": self.role.clone()
         };
-        for i in self.content.clone() {
+        for i in self.content.iter() {
             obj["parts"].push(
                 match i {
                     Part::Text(text) => json::object! {
-                        "text": text
+                        "text": text.as_str()
                     },
                     Part::File(file) => json::object! {
                         "file_data": {
-                            "mime_type": file.mime_type,
-                            "file_uri": file.file_uri
+                            "mime_type": file.mime_type.as_str(),
+                            "file_uri": file.file_uri.as_str()
+                        }
+                    },
+                    Part::InlineData(inline) => json::object! {
+                        "inline_data": {
+                            "mime_type": inline.mime_type.as_str(),
+                            "data": &*inline.data
                         }
                     }
                 }
@@ -85,12 +438,95 @@ pub struct Message {
         };
         obj
     }
+
+    /// Reads a message from the API's `Content` JSON, accepting both `file_data`
+    /// and `fileData` style keys
+    pub fn get_fake(input: &JsonValue) -> Message {
+        let mut content = vec![];
+        for part in input["parts"].members() {
+            if let Some(text) = part["text"].as_str() {
+                content.push(Part::Text(text.to_string()));
+                continue
+            }
+            let inline_data = if part.has_key("inlineData") { &part["inlineData"] } else { &part["inline_data"] };
+            if let (Some(mime_type), Some(data)) = (
+                inline_data["mimeType"].as_str().or(inline_data["mime_type"].as_str()),
+                inline_data["data"].as_str()
+            ) {
+                content.push(Part::InlineData(InlineData {
+                    mime_type: mime_type.to_string(),
+                    data: data.into()
+                }));
+                continue
+            }
+            let file_data = if part.has_key("fileData") { &part["fileData"] } else { &part["file_data"] };
+            let file_uri = file_data["fileUri"].as_str().or(file_data["file_uri"].as_str());
+            let mime_type = file_data["mimeType"].as_str().or(file_data["mime_type"].as_str());
+            if let (Some(file_uri), Some(mime_type)) = (file_uri, mime_type) {
+                content.push(Part::File(GeminiFile {
+                    file_uri: file_uri.to_string(),
+                    mime_type: mime_type.to_string()
+                }));
+            }
+        }
+        Message {
+            content,
+            role: input["role"].as_str().unwrap_or("user").to_string()
+        }
+    }
 }
 
+/// One piece of a message. Text, [GeminiFile]s and [InlineData] convert into it, so a
+/// prompt can be written as `vec!["Describe this".into(), file.into()]`
 #[derive(Debug, Clone)]
 pub enum Part {
     Text(String),
-    File(GeminiFile)
+    /// A file uploaded with [files::upload_file]
+    File(GeminiFile),
+    /// A file sent as part of the request itself, see [files::inline_file]
+    InlineData(InlineData)
+} impl Part {
+    /// Checks for parts the API rejects with unhelpful errors: empty text or data,
+    /// and missing or malformed mime types. [Conversation::generate_content] does
+    /// this for every part before sending.
+    pub fn validate(&self) -> Result<(), GeminiError<'static>> {
+        let invalid = |reason: &str| Err(GeminiError::InvalidPart(reason.to_string()));
+        let valid_mime_type = |mime_type: &str| mime_type.split_once('/').is_some_and(|(kind, sub)| !kind.is_empty() && !sub.is_empty());
+        match self {
+            Part::Text(text) if text.is_empty() => invalid("text is empty"),
+            Part::File(file) if file.file_uri.is_empty() => invalid("file has no URI"),
+            Part::File(file) if !valid_mime_type(&file.mime_type) => invalid(&format!("file has an invalid mime type {0:?}", file.mime_type)),
+            Part::InlineData(inline) if inline.data.is_empty() => invalid("inline data is empty"),
+            Part::InlineData(inline) if !valid_mime_type(&inline.mime_type) => invalid(&format!("inline data has an invalid mime type {0:?}", inline.mime_type)),
+            _ => Ok(())
+        }
+    }
+
+    /// Decodes the bytes of a [Part::InlineData], such as an image generated by Gemini
+    pub fn decode_inline_data(&self) -> Result<Vec<u8>, GeminiError<'static>> {
+        match self {
+            Part::InlineData(inline) => Ok(inline.decode()?),
+            _ => Err(GeminiError::ParseError("Part has no inline data"))
+        }
+    }
+}
+
+impl From<&str> for Part {
+    fn from(text: &str) -> Self {
+        Part::Text(text.to_string())
+    }
+} impl From<String> for Part {
+    fn from(text: String) -> Self {
+        Part::Text(text)
+    }
+} impl From<GeminiFile> for Part {
+    fn from(file: GeminiFile) -> Self {
+        Part::File(file)
+    }
+} impl From<InlineData> for Part {
+    fn from(inline: InlineData) -> Self {
+        Part::InlineData(inline)
+    }
 }
 
 impl<'a> Conversation {
@@ -100,17 +536,192 @@ impl<'a> Conversation {
             token,
             model,
             history: vec![],
-            safety_settings: safety::default_safety_settings()
+            safety_settings: safety::default_safety_settings(),
+            regeneration_policy: None,
+            last_request_id: None,
+            client: Client::new(),
+            api_version: ApiVersion::default(),
+            output: OutputKind::default(),
+            input_guard: None,
+            guard_failure_policy: guard::FailurePolicy::default(),
+            redactor: None,
+            stop_sequences: vec![],
+            metadata: BTreeMap::new(),
+            size_limits: SizeLimits::default(),
+            cached_context: None,
+            recording: None,
+            replay: None,
+            system_instruction: None,
+            language: None,
+            strict_model_version: false,
+            memory: None,
+            memory_error: None,
+            generation_params: GenerationParams::default(),
+        }
+    }
+
+    pub fn set_generation_params(&mut self, params: GenerationParams) {
+        self.generation_params = params;
+    }
+
+    pub fn get_generation_params(&self) -> GenerationParams {
+        self.generation_params
+    }
+
+    /// Fills the generation params that aren't set with the snapshot in `defaults`
+    /// for this conversation's model, so they don't change if the API's defaults do
+    pub fn apply_defaults(&mut self, defaults: &models::ModelDefaults) {
+        if let Some(params) = defaults.get(&self.model) {
+            self.generation_params = self.generation_params.or(params);
         }
     }
 
+    /// Recalls relevant memories before each prompt and remembers each exchange
+    /// afterwards, see [memory]. Up to 3 memories are sent along with a prompt, but
+    /// they aren't kept in the history.
+    pub fn set_memory(&mut self, memory: Option<impl memory::Memory + 'static>) {
+        self.memory = memory.map(|i| Box::new(i) as Box<dyn memory::Memory>);
+    }
+
+    /// The memory set with [Conversation::set_memory], e.g. to store facts directly
+    pub fn memory_mut(&mut self) -> Option<&mut (dyn memory::Memory + 'static)> {
+        self.memory.as_deref_mut()
+    }
+
+    /// Why the last exchange couldn't be stored in the memory, if it couldn't. This
+    /// doesn't fail the prompt, since the reply is already in the history by then.
+    pub fn last_memory_error(&self) -> Option<&GeminiError<'static>> {
+        self.memory_error.as_ref()
+    }
+
+    /// Returns [GeminiError::ModelVersionMismatch] instead of the response when
+    /// [response::GeminiResponse::model_version] isn't the model that was asked for,
+    /// e.g. because an alias now points to a newer version. The prompt is left out of
+    /// the history. Pin an exact version like `gemini-1.5-flash-002` when using this.
+    pub fn set_strict_model_version(&mut self, strict: bool) {
+        self.strict_model_version = strict;
+    }
+
+    /// Instructions for the model that apply to the whole conversation, sent separately
+    /// from the history. Pass `None` to remove them.
+    pub fn set_system_instruction(&mut self, instruction: Option<&str>) {
+        self.system_instruction = instruction.map(str::to_string);
+    }
+
+    /// Asks the model to always reply in `language` (a code like `de` or `pt-BR`),
+    /// whatever language the prompts are in. Replies detected to be in another
+    /// language (see [language::detect_language]) return [GeminiError::LanguageMismatch]
+    /// and are left out of the history, along with their prompt. Pass `None` to unpin.
+    pub fn respond_in(&mut self, language: Option<&str>) {
+        self.language = language.map(str::to_string);
+    }
+
+    /// The `systemInstruction` for requests, combining the system instruction and the
+    /// language from [Conversation::respond_in]
+    pub(crate) fn system_instruction_json(&self) -> Option<JsonValue> {
+        let mut parts = JsonValue::new_array();
+        if let Some(instruction) = &self.system_instruction {
+            let _ = parts.push(json::object! { "text": instruction.as_str() });
+        }
+        if let Some(code) = &self.language {
+            let _ = parts.push(json::object! { "text": format!(
+                "Always respond in {0} ({code}), whatever language the user writes in.",
+                language::language_name(code)
+            ) });
+        }
+        (!parts.is_empty()).then(|| json::object! { "parts": parts })
+    }
+
+    /// Changes the client-side size limits, see [SizeLimits]
+    pub fn set_size_limits(&mut self, limits: SizeLimits) {
+        self.size_limits = limits;
+    }
+
+    /// Makes Gemini stop generating when it outputs any of `sequences`. At most
+    /// 5 non-empty sequences are allowed, pass an empty slice to remove them.
+    pub fn set_stop_sequences(&mut self, sequences: &[&str]) -> Result<(), GeminiError<'static>> {
+        if sequences.len() > MAX_STOP_SEQUENCES {
+            return Err(GeminiError::ConfigError(format!(
+                "At most {MAX_STOP_SEQUENCES} stop sequences are allowed, got {0}", sequences.len()
+            )))
+        }
+        if sequences.iter().any(|i| i.is_empty()) {
+            return Err(GeminiError::ConfigError("Stop sequences can't be empty".to_string()))
+        }
+        self.stop_sequences = sequences.iter().map(|i| i.to_string()).collect();
+        Ok(())
+    }
+
+    /// Redacts the text of every prompt before it is sent and restores it in
+    /// responses, see [redact]
+    pub fn set_redactor(&mut self, redactor: Option<redact::Redactor>) {
+        self.redactor = redactor;
+    }
+
+    /// Screens the text of every prompt before it is sent, see [guard] for whether it
+    /// sees the redacted text
+    pub fn set_input_guard(&mut self, guard: Option<impl guard::InputGuard + 'static>) {
+        self.input_guard = guard.map(|i| Arc::new(i) as Arc<dyn guard::InputGuard>);
+    }
+
+    /// Whether prompts are rejected ([guard::FailurePolicy::Closed], the default) or
+    /// sent anyway when the input guard fails
+    pub fn set_guard_failure_policy(&mut self, policy: guard::FailurePolicy) {
+        self.guard_failure_policy = policy;
+    }
+
+    /// Sets what kind of output to respond with, which configures the response mime
+    /// type, modalities and voice together
+    /// ## Example:
+    /// ```rust
+    /// # use gemini_rs::{Conversation, OutputKind};
+    /// let mut convo = Conversation::new("ABC123".to_string(), "gemini-1.5-flash".to_string());
+    /// convo.output(OutputKind::Json(Some(json::object! {
+    ///     "type": "ARRAY",
+    ///     "items": { "type": "STRING" }
+    /// })));
+    /// ```
+    pub fn output(&mut self, output: OutputKind) {
+        self.output = output;
+    }
+
+    /// Send this conversation's requests to a different API version (`v1beta` by default),
+    /// without affecting the rest of the app
+    pub fn set_api_version(&mut self, version: ApiVersion) {
+        self.api_version = version;
+    }
+
+    /// Use a custom HTTP client, e.g. to tune connection pooling for high throughput.
+    /// Clones of a client share their connection pool, so one client can be shared
+    /// between many conversations.
+    /// ## Example:
+    /// ```rust
+    /// # use std::time::Duration;
+    /// # use gemini_rs::Conversation;
+    /// let client = reqwest::Client::builder()
+    ///     .pool_max_idle_per_host(32)
+    ///     .pool_idle_timeout(Duration::from_secs(90))
+    ///     .http2_keep_alive_interval(Duration::from_secs(30))
+    ///     .tcp_nodelay(true)
+    ///     .build()
+    ///     .unwrap();
+    /// let convo = Conversation::new("ABC123".to_string(), "gemini-1.5-flash".to_string())
+    ///     .with_http_client(client);
+    /// ```
+    pub fn with_http_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
     /// Update the safety settings to different thresholds from [safety::SafetySetting]
     /// ## Example:
-    /// ```rust 
+    /// ```rust
+    /// # use gemini_rs::{Conversation, safety};
     /// let mut convo = Conversation::new(
-    ///     "ABC123".to_string,
-    ///     "gemini-1.5-flash".to_string
-    /// ).update_safety_settings(safety_settings::default());
+    ///     "ABC123".to_string(),
+    ///     "gemini-1.5-flash".to_string()
+    /// );
+    /// convo.update_safety_settings(safety::default_safety_settings());
     /// ```
     pub fn update_safety_settings(&mut self, settings: Vec<safety::SafetySetting>) {
         self.safety_settings = settings;
@@ -118,85 +729,525 @@ impl<'a> Conversation {
 
     pub async fn prompt(&mut self, input: &'a str) -> String {
         match self.generate_content(vec![Part::Text(input.to_string())]).await {
-            Ok(i) => i.get_text(),
+            Ok(i) => i.into_text(),
             Err(e) => format!("{e}")
         }
     }
 
+    /// Switches the model used for the next prompts, e.g. to move from a flash model
+    /// to a pro model for a harder question. History and settings are kept.
+    ///
+    /// A cache made with [Conversation::cache_context] only works with its own model,
+    /// so it stops being used (and expires on its own) when the model changes.
+    pub fn set_model(&mut self, model: String) {
+        if model != self.model {
+            self.cached_context = None;
+        }
+        self.model = model;
+    }
+
+    pub fn get_model(&self) -> &str {
+        &self.model
+    }
+
+    /// Changes the API key used for the next prompts, e.g. when acting on behalf of
+    /// a different customer. History and settings are kept.
+    pub fn set_key(&mut self, token: String) {
+        self.token = token;
+    }
+
+    /// Roughly estimates the tokens in the history without a request, see [estimate_tokens]
+    pub fn estimate_tokens(&self) -> u64 {
+        estimate_tokens(&self.history)
+    }
+
+    /// Counts the tokens in the history exactly, using the countTokens endpoint
+    pub async fn count_tokens(&self) -> Result<u64, GeminiError<'static>> {
+        count_tokens_with(&self.client, self.api_version, &self.token, &self.model, &self.history).await
+    }
+
+    /// The input and output token limits of this conversation's model
+    pub async fn get_token_limits(&self) -> Result<TokenLimits, GeminiError<'static>> {
+        get_token_limits_with(&self.client, self.api_version, &self.token, &self.model).await
+    }
+
+    /// The models available with this conversation's key, see [get_models]
+    pub async fn get_models(&self) -> Result<Vec<String>, GeminiError<'static>> {
+        get_models_with(&self.client, self.api_version, &self.token).await
+    }
+
+    /// Counts the tokens the history would have with `input` added, and compares them
+    /// to the model's limits without sending the prompt. The system instruction isn't
+    /// counted.
+    pub async fn will_fit(&self, input: &[Part]) -> Result<TokenFit, GeminiError<'static>> {
+        let mut contents = self.history.clone();
+        contents.push(Message { content: input.to_vec(), role: "user".to_string() });
+        let (prompt_tokens, limits) = tokio::try_join!(
+            count_tokens_with(&self.client, self.api_version, &self.token, &self.model, &contents),
+            self.get_token_limits()
+        )?;
+        let output_budget = self.generation_params.max_output_tokens
+            .map_or(limits.output_token_limit, |i| i.min(limits.output_token_limit));
+        Ok(TokenFit { prompt_tokens, input_token_limit: limits.input_token_limit, output_budget })
+    }
+
+    /// Same as [Conversation::will_fit], but gives up at `deadline` with
+    /// [GeminiError::DeadlineExceeded], so a slow check can't hold up a prompt
+    pub async fn will_fit_before(&self, input: &[Part], deadline: std::time::Instant) -> Result<TokenFit, GeminiError<'static>> {
+        tokio::time::timeout_at(deadline.into(), self.will_fit(input))
+            .await
+            .unwrap_or(Err(GeminiError::DeadlineExceeded))
+    }
+
+    pub fn get_history(&self) -> &[Message] {
+        &self.history
+    }
+
+    /// Free-form data about the conversation, such as its title, saved along with the history
+    pub fn metadata(&self) -> &BTreeMap<String, String> {
+        &self.metadata
+    }
+
+    pub fn metadata_mut(&mut self) -> &mut BTreeMap<String, String> {
+        &mut self.metadata
+    }
+
+    /// Asks the model for a short title for the conversation so far and stores it in
+    /// the `title` metadata. The history is left unchanged.
+    pub async fn auto_title(&mut self) -> Result<String, GeminiError<'static>> {
+        let mut convo = Conversation::new(self.token.clone(), self.model.clone())
+            .with_http_client(self.client.clone());
+        convo.set_api_version(self.api_version);
+        convo.history = self.history.clone();
+        let response = convo.generate_content(vec![Part::Text(
+            "Reply with only a short title (at most 6 words) for this conversation so far, \
+            without quotes or punctuation at the end.".to_string()
+        )]).await?;
+        let title = response.text().trim().trim_matches(['"', '\'', '*', '.']).trim().to_string();
+        self.metadata.insert("title".to_string(), title.clone());
+        Ok(title)
+    }
+
+    /// Adds messages to the end of the history without sending anything, e.g. from
+    /// [context::ContextBuilder]
+    pub fn extend_history(&mut self, messages: Vec<Message>) {
+        self.history.extend(messages);
+    }
+
+    /// Regenerate responses that stop because of recitation or come back empty,
+    /// see [response::RegenerationPolicy]. Pass `None` to turn it off again.
+    pub fn set_regeneration_policy(&mut self, policy: Option<response::RegenerationPolicy>) {
+        self.regeneration_policy = policy;
+    }
+
     /// Sends a prompt to the Gemini API and returns the response
-    pub async fn generate_content(&mut self, input: Vec<Part>) -> Result<GeminiResponse, GeminiError> {
-        let model_verified = verify_inputs(&self.model, &self.token).await;
-        if let Err(ref _e) = model_verified { return Err(model_verified.unwrap_err()) };
+    pub async fn generate_content(&mut self, input: Vec<Part>) -> Result<GeminiResponse, GeminiError<'static>> {
+        self.generate_content_with(input, None).await
+    }
+
+    async fn generate_content_with(
+        &mut self,
+        input: Vec<Part>,
+        deadline: Option<std::time::Instant>
+    ) -> Result<GeminiResponse, GeminiError<'static>> {
+        if input.is_empty() {
+            return Err(GeminiError::InvalidPart("the prompt has no parts".to_string()))
+        }
+        for (i, part) in input.iter().enumerate() {
+            if let Err(GeminiError::InvalidPart(reason)) = part.validate() {
+                return Err(GeminiError::InvalidPart(format!("part {i}: {reason}")))
+            }
+        }
+        for part in &input {
+            if let Part::InlineData(inline) = part {
+                SizeLimits::check(error::SizeLimit::InlineData, inline.data.len() / 4 * 3, self.size_limits.max_inline_bytes)?;
+            }
+        }
+
+        if !self.is_replaying() {
+            let model_verified = verify_inputs(&self.client, self.api_version, &self.model, &self.token).await;
+            if let Err(ref _e) = model_verified { return Err(model_verified.unwrap_err()) };
+        }
 
+        // Local guards can reject prompts for what the redactor hides, but remote ones
+        // must not see it
+        let remote_guard = self.input_guard.as_ref().is_some_and(|i| i.is_remote());
+        if !remote_guard {
+            self.check_input(&input).await?;
+        }
+        let input = match &mut self.redactor {
+            Some(redactor) => redactor.redact_parts(input),
+            None => input,
+        };
+        if remote_guard {
+            self.check_input(&input).await?;
+        }
+
+        let prompt_text: String = input.iter().filter_map(|part| match part {
+            Part::Text(text) => Some(text.as_str()),
+            _ => None
+        }).collect();
+        // Only sent with this request, so recalled memories don't pile up in the history
+        let preamble = self.memory.as_ref()
+            .map(|memory| memory.search(&prompt_text, 3))
+            .filter(|memories| !memories.is_empty())
+            .map(|memories| {
+                let recalled: Vec<String> = memories.iter().map(|i| format!("- {0}", i.text)).collect();
+                format!("Things you remember that may be relevant:\n{0}", recalled.join("\n"))
+            });
+        let extras = RequestExtras { preamble, deadline, ..Default::default() };
+
+        let history_len = self.history.len();
         self.history.push(
-            Message { content: input.clone(), role: "user".to_string() }
+            Message { content: input, role: "user".to_string() }
         );
 
-        let url = format!(
-            "https://generativelanguage.googleapis.com/v1beta/models/{0}:generateContent?key={1}",
-            self.model, self.token
+        let mut response = self.send_with_regeneration(&extras).await?;
+
+        if let Some(served) = response.model_version.as_ref().filter(|_| self.strict_model_version) {
+            if served.trim_start_matches("models/") != self.model {
+                self.history.truncate(history_len);
+                return Err(GeminiError::ModelVersionMismatch { requested: self.model.clone(), served: served.clone() })
+            }
+        }
+
+        if let Some(expected) = &self.language {
+            let detected = language::detect_language(&response.text());
+            if let Some(detected) = detected.filter(|i| *i != language::primary_subtag(expected)) {
+                self.history.truncate(history_len);
+                return Err(GeminiError::LanguageMismatch { expected: expected.clone(), detected: detected.to_string() })
+            }
+        }
+
+        self.history.push(
+            Message { content: response.content.clone(), role: "model".to_string() }
         );
 
+        if let Some(memory) = &mut self.memory {
+            let exchange = format!("User said: {prompt_text}\nYou replied: {0}", response.text());
+            self.memory_error = memory.store(&response.request_id, &exchange).err();
+        }
+
+        if let Some(redactor) = &self.redactor {
+            response.content = redactor.restore_parts(response.content);
+        }
+        Ok(response)
+    }
+
+    /// Runs the input guard on the text of `input`, see [guard]
+    async fn check_input(&self, input: &[Part]) -> Result<(), GeminiError<'static>> {
+        let Some(guard) = &self.input_guard else { return Ok(()) };
+        let text = input.iter().filter_map(|part| match part {
+            Part::Text(text) => Some(text.as_str()),
+            _ => None
+        }).collect::<Vec<_>>().join("\n");
+        match (guard.check(&text).await, self.guard_failure_policy) {
+            (Ok(guard::Verdict::Reject(reason)), _) => Err(GeminiError::Rejected(reason)),
+            (Err(e), guard::FailurePolicy::Closed) => {
+                Err(GeminiError::Rejected(format!("Input guard failed: {0}", e.to_string_without_url())))
+            },
+            (Ok(guard::Verdict::Allow), _) | (Err(_), guard::FailurePolicy::Open) => Ok(()),
+        }
+    }
+
+    /// Sends the history, regenerating the response as the regeneration policy says
+    async fn send_with_regeneration(&mut self, extras: &RequestExtras) -> Result<GeminiResponse, GeminiError<'static>> {
+        let mut response = self.send_history_with_id(extras).await?;
+        let mut retries = 0;
+        let mut extras = extras.clone();
+        while let Some(policy) = &self.regeneration_policy {
+            if retries >= policy.max_retries || !policy.should_regenerate(&response) { break }
+            retries += 1;
+            // Only in the retried requests, never in the history
+            extras.postscript = policy.rephrase_instruction.clone();
+            extras.temperature = policy.retry_temperature(self.generation_params.temperature, retries);
+            response = self.send_history_with_id(&extras).await?;
+        }
+        Ok(response)
+    }
+
+    /// Shows the exact URL and JSON body that prompting
+    /// `input` would send, without sending anything or changing the history
+    pub fn request_json(&self, input: Vec<Part>) -> Result<RequestPreview, GeminiError<'static>> {
+        let mut contents: Vec<&Message> = self.history.iter().collect();
+        let message = Message { content: input, role: "user".to_string() };
+        contents.push(&message);
+        Ok(RequestPreview {
+            url: self.generate_content_url(),
+            body: self.request_body(contents, &RequestExtras::default())?,
+        })
+    }
+
+    /// Checks the API key and model like a real prompt would, then returns what would
+    /// have been sent (see [Conversation::request_json]) without generating anything
+    pub async fn dry_run(&self, input: Vec<Part>) -> Result<RequestPreview, GeminiError<'static>> {
+        verify_inputs(&self.client, self.api_version, &self.model, &self.token).await?;
+        self.request_json(input)
+    }
+
+    fn generate_content_url(&self) -> String {
+        format!(
+            "https://generativelanguage.googleapis.com/{0}/models/{1}:generateContent",
+            self.api_version.get_real(), self.model
+        )
+    }
+
+    fn request_body<'m>(
+        &self,
+        contents: impl IntoIterator<Item = &'m Message>,
+        extras: &RequestExtras
+    ) -> Result<JsonValue, json::Error> {
         let mut data = json::object! {
             "safetySettings": [],
             "contents": []
         };
-        for i in self.history.iter() {
+        for i in contents.into_iter().skip(self.cached_messages()) {
             data["contents"].push(i.get_real())?
         };
+        if !data["contents"].is_empty() && (extras.preamble.is_some() || extras.postscript.is_some()) {
+            let last = data["contents"].len() - 1;
+            let mut parts = JsonValue::new_array();
+            if let Some(preamble) = &extras.preamble {
+                parts.push(json::object! { "text": preamble.as_str() })?;
+            }
+            for part in data["contents"][last]["parts"].members() {
+                parts.push(part.clone())?;
+            }
+            if let Some(postscript) = &extras.postscript {
+                parts.push(json::object! { "text": postscript.as_str() })?;
+            }
+            data["contents"][last]["parts"] = parts;
+        }
+        if let Some(name) = self.cached_context_name().filter(|_| self.cached_messages() > 0) {
+            data["cachedContent"] = name.into();
+        } else if let Some(instruction) = self.system_instruction_json() {
+            // Cached content already includes the system instruction
+            data["systemInstruction"] = instruction;
+        }
         for i in &self.safety_settings {
             data["safetySettings"].push(json::object! {
                 "category": i.category.get_real(),
                 "threshold": i.threshold.get_real()
             })?
         };
+        let mut config = self.output.get_real().unwrap_or_else(|| json::object! {});
+        for (key, value) in self.generation_params.get_real().entries() {
+            config[key] = value.clone();
+        }
+        if let Some(temperature) = extras.temperature {
+            config["temperature"] = temperature.into();
+        }
+        if !self.stop_sequences.is_empty() {
+            config["stopSequences"] = self.stop_sequences.clone().into();
+        }
+        if !config.is_empty() {
+            data["generationConfig"] = config;
+        }
+        Ok(data)
+    }
+
+    /// Same as [Conversation::generate_content], but if the request fails the history is
+    /// rolled back to how it was before, so the failed prompt isn't left behind
+    /// without a reply and the call can simply be retried
+    pub async fn generate_content_idempotent(&mut self, input: Vec<Part>) -> Result<GeminiResponse, GeminiError<'static>> {
+        let history_len = self.history.len();
+        let result = self.generate_content(input).await;
+        if result.is_err() {
+            self.history.truncate(history_len);
+        }
+        result
+    }
+
+    /// Same as [Conversation::generate_content], but gives up as soon as `cancel` finishes,
+    /// e.g. when a user presses stop. The request is dropped right away and the
+    /// history is left as it was before the call.
+    ///
+    /// Any future works, such as `tokio_util::sync::CancellationToken::cancelled()`
+    /// or a `tokio::sync::oneshot::Receiver`.
+    pub async fn generate_content_until(
+        &mut self,
+        input: Vec<Part>,
+        cancel: impl Future<Output = ()>
+    ) -> Result<GeminiResponse, GeminiError<'static>> {
+        self.generate_content_cancellable(input, cancel, None).await
+    }
+
+    async fn generate_content_cancellable(
+        &mut self,
+        input: Vec<Part>,
+        cancel: impl Future<Output = ()>,
+        deadline: Option<std::time::Instant>
+    ) -> Result<GeminiResponse, GeminiError<'static>> {
+        let history_len = self.history.len();
+        let result = tokio::select! {
+            result = self.generate_content_with(input, deadline) => Some(result),
+            _ = cancel => None
+        };
+        result.unwrap_or_else(|| {
+            self.history.truncate(history_len);
+            Err(GeminiError::Cancelled)
+        })
+    }
+
+    /// Same as [Conversation::generate_content_until], but gives up at `deadline` with
+    /// [GeminiError::DeadlineExceeded]. The time left is also sent in the
+    /// `X-Server-Timeout` header, so the server can stop working on it too.
+    pub async fn generate_content_before(
+        &mut self,
+        input: Vec<Part>,
+        deadline: std::time::Instant
+    ) -> Result<GeminiResponse, GeminiError<'static>> {
+        if deadline <= std::time::Instant::now() {
+            return Err(GeminiError::DeadlineExceeded)
+        }
+        let result = self.generate_content_cancellable(input, tokio::time::sleep_until(deadline.into()), Some(deadline)).await;
+        match result {
+            Err(GeminiError::Cancelled) => Err(GeminiError::DeadlineExceeded),
+            result => result
+        }
+    }
+
+    /// The id sent with the most recent request, including ones that failed.
+    ///
+    /// Every request gets a new id in the `X-Request-Id` header, which can be used
+    /// to match failures up with your own logs or a support ticket.
+    pub fn last_request_id(&self) -> Option<&str> {
+        self.last_request_id.as_deref()
+    }
+
+    async fn send_history_with_id(&mut self, extras: &RequestExtras) -> Result<GeminiResponse, GeminiError<'static>> {
+        if !self.is_replaying() {
+            self.refresh_cached_context().await?;
+        }
+        let request_id = new_request_id();
+        self.last_request_id = Some(request_id.clone());
+        let data = self.request_body(&self.history, extras)?.dump();
 
-        let client = Client::new();
-        let request = client
+        if let Some(replayer) = &mut self.replay {
+            let result = replayer.respond(&data).and_then(|r| parse_response(r, request_id.clone()));
+            return result.map_err(|e| e.with_request_id(&request_id))
+        }
+
+        let started = std::time::Instant::now();
+        let result = match self.send_history(data.clone(), &request_id, extras.deadline).await {
+            Ok(response_dict) => {
+                if let Some(recording) = &mut self.recording {
+                    recording.entries.push(replay::TranscriptEntry {
+                        request_hash: replay::request_hash(&data),
+                        response: response_dict.clone(),
+                    });
+                }
+                parse_response(response_dict, request_id.clone())
+            },
+            Err(e) => Err(e),
+        }.map_err(|e| e.with_request_id(&request_id));
+        telemetry::record_request(&self.model, started.elapsed(), &result);
+        result
+    }
+
+    async fn send_history(
+        &self,
+        data: String,
+        request_id: &str,
+        deadline: Option<std::time::Instant>
+    ) -> Result<JsonValue, GeminiError<'static>> {
+        let url = self.generate_content_url();
+        SizeLimits::check(error::SizeLimit::Request, data.len(), self.size_limits.max_request_bytes)?;
+
+        let client = &self.client;
+        let mut request = client
             .request(Method::POST, url)
+            .header(API_KEY_HEADER, &self.token)
             .header("Content-Type", "application/json")
-            .body(data.dump())
-            .build()?;
+            .header("X-Request-Id", request_id);
+        let remaining = deadline.map(|i| i.saturating_duration_since(std::time::Instant::now()));
+        if let Some(remaining) = remaining.filter(|i| !i.is_zero()) {
+            request = request.header("X-Server-Timeout", remaining.as_secs().max(1).to_string());
+        }
+        let request = request.body(data).build()?;
 
         let http_response = client.execute(request).await?;
+        let max_response = self.size_limits.max_response_bytes;
+        if let Some(length) = http_response.content_length() {
+            SizeLimits::check(error::SizeLimit::Response, length as usize, max_response)?;
+        }
         let response_json = http_response.text().await?;
-        let response_dict = json::parse(&response_json)?;
-        let candidate = response_dict["candidates"][0].clone();
-        let token_count = response_dict["usageMetadata"]["candidatesTokenCount"]
-            .as_u64()
-            .ok_or_else(|| GeminiError::ParseError("Failed to extract token count"))?;
-        let finish_reason = response::FinishReason::get_fake(candidate["finishReason"].as_str().unwrap());
+        SizeLimits::check(error::SizeLimit::Response, response_json.len(), max_response)?;
+        Ok(json::parse(&response_json)?)
+    }
+}
 
-        let parts_dict = candidate["content"]["parts"].clone();
-        let mut content = vec![]; 
-        for i in parts_dict.members() {
-            let part = Part::Text(i["text"].as_str().unwrap().to_string());
-            content.push(part)
-        }
+/// What is sent with the requests for one prompt without being part of the conversation.
+/// Kept out of [Conversation] so nothing is left behind when a request fails or is cancelled.
+#[derive(Debug, Clone, Default)]
+struct RequestExtras {
+    /// Text put before the prompt, like recalled memories
+    preamble: Option<String>,
+    /// Text put after the prompt, like a [response::RegenerationPolicy]'s rephrase instruction
+    postscript: Option<String>,
+    /// Replaces the conversation's temperature
+    temperature: Option<f64>,
+    /// Sent in the `X-Server-Timeout` header
+    deadline: Option<std::time::Instant>,
+}
 
-        let mut safety_rating = vec![];
-        for i in candidate["safetyRatings"].members() {
-            safety_rating.push(safety::SafetyRating {
-                category: safety::HarmCategory::get_fake(
-                    i["category"].as_str().unwrap()
-                ),
-                probability: safety::HarmProbability::get_fake(
-                    i["probability"].as_str().unwrap()
-                )
-            })
-        }
+fn parse_response(response_dict: JsonValue, request_id: String) -> Result<GeminiResponse, GeminiError<'static>> {
+    check_api_error(&response_dict)?;
+    let candidate = response_dict["candidates"][0].clone();
+    let usage = &response_dict["usageMetadata"];
+    if usage.is_null() {
+        return Err(GeminiError::ParseError("Failed to extract token count"))
+    }
+    // Missing when the prompt was blocked and no candidate came back
+    let token_count = usage["candidatesTokenCount"].as_u64().unwrap_or(0);
+    let finish_reason = response::FinishReason::get_fake(candidate["finishReason"].as_str().unwrap_or(""));
 
-        self.history.push(
-            Message { content: content.clone(), role: "model".to_string() }
-        );
+    let content = Message::get_fake(&candidate["content"]).content;
 
-        Ok(GeminiResponse {
-            content,
-            safety_rating,
-            token_count,
-            finish_reason,
-        })
-    }
+    let safety_rating = candidate["safetyRatings"].members().map(safety::SafetyRating::get_fake).collect();
+    let prompt_feedback = response_dict["promptFeedback"].is_object()
+        .then(|| safety::PromptFeedback::get_fake(&response_dict["promptFeedback"]));
+
+    Ok(GeminiResponse {
+        content,
+        safety_rating,
+        token_count,
+        finish_reason,
+        request_id,
+        prompt_feedback,
+        citations: candidate["citationMetadata"]["citationSources"]
+            .members()
+            .map(response::Citation::get_fake)
+            .collect(),
+        model_version: response_dict["modelVersion"].as_str().map(str::to_string),
+        #[cfg(feature = "raw-response")]
+        raw: response_dict,
+    })
+}
+
+/// Sends a single prompt without keeping any history and returns the text of the response
+/// ## Example:
+/// ```rust,no_run
+/// # async fn run() {
+/// let api_key = std::env::var("GEMINI_API_KEY").unwrap();
+/// let answer = gemini_rs::prompt(&api_key, "gemini-1.5-flash", "Name a colour").await.unwrap();
+/// println!("{answer}");
+/// # }
+/// ```
+pub async fn prompt(token: &str, model: &str, input: &str) -> Result<String, GeminiError<'static>> {
+    prompt_with_config(token, model, input, safety::default_safety_settings()).await
+}
+
+/// Same as [prompt], but with custom safety settings
+pub async fn prompt_with_config(
+    token: &str,
+    model: &str,
+    input: &str,
+    safety_settings: Vec<safety::SafetySetting>
+) -> Result<String, GeminiError<'static>> {
+    let mut convo = Conversation::new(token.to_string(), model.to_string());
+    convo.update_safety_settings(safety_settings);
+    Ok(convo.generate_content(vec![Part::Text(input.to_string())]).await?.into_text())
 }
 
 /// Get available models
@@ -208,18 +1259,149 @@ impl<'a> Conversation {
 /// - `gemini-1.5-flash`
 /// - `gemini-1.5-pro`
 /// - `gemini-1.0-pro`
-pub async fn get_models(token: &str) -> Result<Vec<String>, GeminiError> {
-    let request = reqwest::get(format!(
-        "https://generativelanguage.googleapis.com/v1beta/models?key={0}",
-        token
-    )).await?.text().await?;
+pub async fn get_models(token: &str) -> Result<Vec<String>, GeminiError<'_>> {
+    get_models_with(&Client::new(), ApiVersion::default(), token).await
+}
+
+/// [get_models] with a specific client and API version, e.g. a conversation's
+pub(crate) async fn get_models_with(client: &Client, version: ApiVersion, token: &str) -> Result<Vec<String>, GeminiError<'static>> {
+    let request = client
+        .get(format!("https://generativelanguage.googleapis.com/{0}/models", version.get_real()))
+        .header(API_KEY_HEADER, token)
+        .send().await?.text().await?;
+    let response_json = json::parse(&request)?;
+    check_api_error(&response_json)?;
+    Ok(format_models(response_json))
+}
+
+/// Input and output token limits of a model
+#[derive(Debug, Clone, Copy)]
+pub struct TokenLimits {
+    pub input_token_limit: u64,
+    pub output_token_limit: u64,
+}
+
+/// How a prompt measures up against a model's limits, see [Conversation::will_fit]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TokenFit {
+    /// Tokens in the history plus the new prompt
+    pub prompt_tokens: u64,
+    pub input_token_limit: u64,
+    /// The most tokens the reply can have: the model's output limit, or
+    /// [GenerationParams::max_output_tokens] if that is lower
+    pub output_budget: u64,
+} impl TokenFit {
+    /// Whether the prompt is within the model's input limit
+    pub fn fits(&self) -> bool {
+        self.prompt_tokens <= self.input_token_limit
+    }
+
+    /// Input tokens left for later prompts, 0 if it doesn't fit
+    pub fn remaining_input(&self) -> u64 {
+        self.input_token_limit.saturating_sub(self.prompt_tokens)
+    }
+}
+
+/// Get the input and output token limits of `model`
+pub async fn get_token_limits(token: &str, model: &str) -> Result<TokenLimits, GeminiError<'static>> {
+    get_token_limits_with(&Client::new(), ApiVersion::default(), token, model).await
+}
+
+/// [get_token_limits] with a specific client and API version, e.g. a conversation's
+pub(crate) async fn get_token_limits_with(
+    client: &Client,
+    version: ApiVersion,
+    token: &str,
+    model: &str
+) -> Result<TokenLimits, GeminiError<'static>> {
+    let request = client
+        .get(format!("https://generativelanguage.googleapis.com/{0}/models/{model}", version.get_real()))
+        .header(API_KEY_HEADER, token)
+        .send().await?.text().await?;
     let response_json = json::parse(&request)?;
-    let models = format_models(response_json);
+    check_api_error(&response_json)?;
 
-    Ok(models) 
+    Ok(TokenLimits {
+        input_token_limit: response_json["inputTokenLimit"]
+            .as_u64()
+            .ok_or(GeminiError::ParseError("Failed to extract input token limit"))?,
+        output_token_limit: response_json["outputTokenLimit"]
+            .as_u64()
+            .ok_or(GeminiError::ParseError("Failed to extract output token limit"))?,
+    })
+}
+
+/// Roughly estimates how many tokens `contents` take up, without any requests.
+///
+/// Text counts as one token per 4 characters, and every file or inline data part
+/// as 258 tokens (the cost of a small image). Use [count_tokens] when the number
+/// has to be exact.
+pub fn estimate_tokens(contents: &[Message]) -> u64 {
+    contents.iter().flat_map(|i| i.content.iter()).map(|part| match part {
+        Part::Text(text) => (text.chars().count() as u64).div_ceil(4),
+        Part::File(_) | Part::InlineData(_) => 258,
+    }).sum()
 }
 
-fn format_models(input: JsonValue) -> Vec<String> {
+/// Count how many tokens `contents` take up for `model`, using the countTokens endpoint
+pub async fn count_tokens(token: &str, model: &str, contents: &[Message]) -> Result<u64, GeminiError<'static>> {
+    count_tokens_with(&Client::new(), ApiVersion::default(), token, model, contents).await
+}
+
+/// [count_tokens] with a specific client and API version, e.g. a conversation's
+pub(crate) async fn count_tokens_with(
+    client: &Client,
+    version: ApiVersion,
+    token: &str,
+    model: &str,
+    contents: &[Message]
+) -> Result<u64, GeminiError<'static>> {
+    let url = format!(
+        "https://generativelanguage.googleapis.com/{0}/models/{1}:countTokens",
+        version.get_real(), model
+    );
+    let mut data = json::object! {
+        "contents": []
+    };
+    for i in contents {
+        data["contents"].push(i.get_real())?
+    };
+
+    let response_json = client
+        .request(Method::POST, url)
+        .header(API_KEY_HEADER, token)
+        .header("Content-Type", "application/json")
+        .body(data.dump())
+        .send()
+        .await?
+        .text()
+        .await?;
+    let response_dict = json::parse(&response_json)?;
+    check_api_error(&response_dict)?;
+
+    response_dict["totalTokens"]
+        .as_u64()
+        .ok_or(GeminiError::ParseError("Failed to extract token count"))
+}
+
+/// Makes an id that is unique for this process and very unlikely to repeat across processes
+fn new_request_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|i| i.as_nanos()).unwrap_or(0);
+    format!("{nanos:x}-{0:x}-{1:x}", std::process::id(), COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+pub(crate) fn check_api_error(response: &JsonValue) -> Result<(), GeminiError<'static>> {
+    if let Some(quota_failure) = error::QuotaFailure::get_fake(&response["error"]) {
+        return Err(GeminiError::QuotaError(quota_failure));
+    }
+    if response.has_key("error") {
+        return Err(GeminiError::ApiError(error::ApiError::get_fake(&response["error"])));
+    }
+    Ok(())
+}
+
+pub(crate) fn format_models(input: JsonValue) -> Vec<String> {
     let mut models: Vec<String> = vec![];
     for i in input["models"].members() {
         models.push(i["name"].to_string().strip_prefix("models/").unwrap().to_string());
@@ -227,19 +1409,8 @@ fn format_models(input: JsonValue) -> Vec<String> {
     models
 }
 
-async fn verify_inputs<'a>(model_name: &'a str, token: &'a str) -> Result<(), GeminiError<'a>> {
-    //let models = get_models(token).await.unwrap();
-    //models.contains(&model_name.to_string())
-    let request = reqwest::get(format!(
-        "https://generativelanguage.googleapis.com/v1beta/models?key={0}",
-        token
-    )).await?.text().await?;
-    let response_json = json::parse(&request)?;
-    if response_json.has_key("error") {
-        println!("{0}", response_json["error"].dump());
-        return Err(GeminiError::KeyError(format!("{0}: {1}", response_json["error"]["code"], response_json["error"]["message"])));
-    };
-    let models = format_models(response_json);
+async fn verify_inputs(client: &Client, version: ApiVersion, model_name: &str, token: &str) -> Result<(), GeminiError<'static>> {
+    let models = get_models_with(client, version, token).await?;
     if !models.contains(&model_name.to_string()) {
         return Err(GeminiError::ModelError("Invalid model. Please pass a valid model from get_models()"))
     }
@@ -0,0 +1,140 @@
+//! Long-term memory for a conversation
+//!
+//! Set one with [crate::Conversation::set_memory]. Before each prompt the memory is
+//! searched with its text and the best matches are added to it, and after each reply
+//! the exchange is stored, so an assistant can recall earlier sessions.
+//! ## Example:
+//! ```rust
+//! # use gemini_rs::memory::{FileMemory, Memory};
+//! # let path = std::env::temp_dir().join("gemini-rs-memory-doctest.json");
+//! let mut memory = FileMemory::open(&path).unwrap();
+//! memory.store("favourite-colour", "The user's favourite colour is green").unwrap();
+//! let found = memory.search("what colour should the logo be", 3);
+//! assert_eq!(found[0].key, "favourite-colour");
+//! # std::fs::remove_file(path).unwrap();
+//! ```
+use std::path::{Path, PathBuf};
+
+use json::JsonValue;
+
+use crate::GeminiError;
+
+/// A remembered piece of text
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryEntry {
+    pub key: String,
+    pub text: String,
+}
+
+/// Stores text by key and finds it again by key or by a query
+pub trait Memory: Send + Sync {
+    /// Stores `text`, replacing anything already stored under `key`
+    fn store(&mut self, key: &str, text: &str) -> Result<(), GeminiError<'static>>;
+
+    fn get(&self, key: &str) -> Option<String>;
+
+    /// Up to `limit` entries relevant to `query`, best first
+    fn search(&self, query: &str, limit: usize) -> Vec<MemoryEntry>;
+}
+
+/// Keeps entries in memory and searches them by the words they share with the query
+#[derive(Debug, Clone, Default)]
+pub struct InMemory {
+    entries: Vec<MemoryEntry>,
+} impl InMemory {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn entries(&self) -> &[MemoryEntry] {
+        &self.entries
+    }
+
+    pub fn get_real(&self) -> JsonValue {
+        let mut entries = JsonValue::new_array();
+        for i in &self.entries {
+            let _ = entries.push(json::object! { "key": i.key.as_str(), "text": i.text.as_str() });
+        }
+        entries
+    }
+
+    pub fn get_fake(input: &JsonValue) -> InMemory {
+        InMemory {
+            entries: input.members().map(|i| MemoryEntry {
+                key: i["key"].as_str().unwrap_or("").to_string(),
+                text: i["text"].as_str().unwrap_or("").to_string(),
+            }).collect()
+        }
+    }
+} impl Memory for InMemory {
+    fn store(&mut self, key: &str, text: &str) -> Result<(), GeminiError<'static>> {
+        let entry = MemoryEntry { key: key.to_string(), text: text.to_string() };
+        match self.entries.iter_mut().find(|i| i.key == key) {
+            Some(existing) => *existing = entry,
+            None => self.entries.push(entry),
+        }
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        self.entries.iter().find(|i| i.key == key).map(|i| i.text.clone())
+    }
+
+    fn search(&self, query: &str, limit: usize) -> Vec<MemoryEntry> {
+        let query = words(query);
+        let mut scored: Vec<(usize, &MemoryEntry)> = self.entries.iter()
+            .map(|entry| {
+                let text = words(&format!("{0} {1}", entry.key, entry.text));
+                (query.iter().filter(|i| text.contains(i)).count(), entry)
+            })
+            .filter(|(score, _)| *score > 0)
+            .collect();
+        // Stable, so equally relevant entries keep the order they were stored in
+        scored.sort_by_key(|(score, _)| std::cmp::Reverse(*score));
+        scored.into_iter().take(limit).map(|(_, entry)| entry.clone()).collect()
+    }
+}
+
+/// An [InMemory] saved to a JSON file after every change
+#[derive(Debug, Clone)]
+pub struct FileMemory {
+    path: PathBuf,
+    memory: InMemory,
+} impl FileMemory {
+    /// Loads the file at `path`, or starts empty if it doesn't exist yet
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, GeminiError<'static>> {
+        let path = path.as_ref().to_path_buf();
+        let memory = match std::fs::read_to_string(&path) {
+            Ok(contents) => InMemory::get_fake(&json::parse(&contents)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => InMemory::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self { path, memory })
+    }
+} impl Memory for FileMemory {
+    fn store(&mut self, key: &str, text: &str) -> Result<(), GeminiError<'static>> {
+        self.memory.store(key, text)?;
+        std::fs::write(&self.path, self.memory.get_real().dump())?;
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Option<String> {
+        self.memory.get(key)
+    }
+
+    fn search(&self, query: &str, limit: usize) -> Vec<MemoryEntry> {
+        self.memory.search(query, limit)
+    }
+}
+
+/// Lowercase words of at least 3 letters, so filler like "a" or "is" doesn't match everything
+fn words(text: &str) -> Vec<String> {
+    let mut words: Vec<String> = text
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|i| i.chars().count() >= 3)
+        .map(str::to_lowercase)
+        .collect();
+    words.sort();
+    words.dedup();
+    words
+}
@@ -0,0 +1,244 @@
+//! Resolves model aliases like `flash-latest` to concrete model versions
+//!
+//! The list of models is fetched once and cached, so apps can ask for the newest
+//! model of a family without hardcoding a version that may be retired later.
+//! ## Example:
+//! ```rust,no_run
+//! # use gemini_rs::models::ModelResolver;
+//! # async fn run() -> Result<(), gemini_rs::GeminiError<'static>> {
+//! let api_key = std::env::var("GEMINI_API_KEY").unwrap();
+//! let resolver = ModelResolver::new(api_key)
+//!     .on_missing(|model| eprintln!("warning: {model} is no longer listed"));
+//! // e.g. "gemini-2.0-flash-001"
+//! let model = resolver.resolve("flash-latest").await?;
+//! let mut convo = resolver.conversation("gemini-1.5-pro-002").await?;
+//! # Ok(())
+//! # }
+//! ```
+use std::{fmt, time::Duration};
+
+use json::JsonValue;
+use tokio::{sync::Mutex, time::Instant};
+
+use crate::{check_api_error, format_models, Conversation, GeminiError, GenerationParams};
+
+type MissingHook = Box<dyn Fn(&str) + Send + Sync>;
+
+/// Resolves and checks model names against a cached list of models, see the
+/// [module docs](self)
+pub struct ModelResolver {
+    token: String,
+    client: reqwest::Client,
+    cache_for: Duration,
+    aliases: Vec<(String, String)>,
+    on_missing: Option<MissingHook>,
+    cache: Mutex<Option<(Instant, Vec<String>)>>,
+} impl fmt::Debug for ModelResolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ModelResolver")
+            .field("token", &"[REDACTED]")
+            .field("cache_for", &self.cache_for)
+            .field("aliases", &self.aliases)
+            .field("on_missing", &self.on_missing.is_some())
+            .finish()
+    }
+}
+
+impl ModelResolver {
+    /// Creates a resolver that refreshes its list of models every hour
+    pub fn new(token: String) -> Self {
+        Self {
+            token,
+            client: reqwest::Client::new(),
+            cache_for: Duration::from_secs(60 * 60),
+            aliases: vec![],
+            on_missing: None,
+            cache: Mutex::new(None),
+        }
+    }
+
+    /// How long the list of models is kept before fetching it again
+    pub fn cache_for(mut self, duration: Duration) -> Self {
+        self.cache_for = duration;
+        self
+    }
+
+    /// Adds a custom alias, checked before the built-in `<family>-latest` ones
+    pub fn alias(mut self, alias: &str, model: &str) -> Self {
+        self.aliases.push((alias.to_string(), model.to_string()));
+        self
+    }
+
+    /// Called with the model name when a pinned model isn't listed anymore. The
+    /// model is still returned, as it may keep working for a while.
+    pub fn on_missing(mut self, hook: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        self.on_missing = Some(Box::new(hook));
+        self
+    }
+
+    pub fn http_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Turns `model` into a concrete model name.
+    ///
+    /// Custom aliases are replaced first. Then `<family>-latest` (e.g. `flash-latest`,
+    /// `pro-latest`, `flash-lite-latest`) becomes the newest listed `gemini-<version>-<family>`,
+    /// preferring numbered revisions. Previews and experimental models are never picked.
+    /// Any other name is checked against the list and returned as is.
+    pub async fn resolve(&self, model: &str) -> Result<String, GeminiError<'static>> {
+        let model = self.aliases.iter()
+            .find(|(alias, _)| alias == model)
+            .map_or(model, |(_, target)| target.as_str())
+            .trim_start_matches("models/");
+        let models = self.models().await?;
+
+        if let Some(family) = model.strip_suffix("-latest").filter(|_| !models.iter().any(|i| i == model)) {
+            let family = family.trim_start_matches("gemini-");
+            return models.iter()
+                .filter_map(|name| Some((version_of(name, family)?, name)))
+                .max()
+                .map(|(_, name)| name.clone())
+                .ok_or_else(|| GeminiError::ModelError("No listed model matches this alias"))
+        }
+
+        if !models.iter().any(|i| i == model) {
+            if let Some(hook) = &self.on_missing {
+                hook(model);
+            }
+        }
+        Ok(model.to_string())
+    }
+
+    /// Creates a conversation with the resolved model
+    pub async fn conversation(&self, model: &str) -> Result<Conversation, GeminiError<'static>> {
+        let model = self.resolve(model).await?;
+        Ok(Conversation::new(self.token.clone(), model).with_http_client(self.client.clone()))
+    }
+
+    /// Every listed model, fetched again if the cache is older than [ModelResolver::cache_for]
+    pub async fn models(&self) -> Result<Vec<String>, GeminiError<'static>> {
+        let mut cache = self.cache.lock().await;
+        if let Some((fetched, models)) = &*cache {
+            if fetched.elapsed() < self.cache_for {
+                return Ok(models.clone())
+            }
+        }
+        let request = self.client
+            .get("https://generativelanguage.googleapis.com/v1beta/models?pageSize=1000")
+            .header(crate::API_KEY_HEADER, &self.token)
+            .send().await?.text().await?;
+        let response_json = json::parse(&request)?;
+        check_api_error(&response_json)?;
+        let models = format_models(response_json);
+        *cache = Some((Instant::now(), models.clone()));
+        Ok(models)
+    }
+
+    /// Forgets the cached list of models
+    pub async fn refresh(&self) {
+        *self.cache.lock().await = None;
+    }
+}
+
+/// The version and revision of `name` if it is `gemini-<version>-<family>` or
+/// `gemini-<version>-<family>-<revision>`, e.g. `([2, 0], 1)` for `gemini-2.0-flash-001`
+fn version_of(name: &str, family: &str) -> Option<(Vec<u32>, u32)> {
+    let (version, rest) = name.strip_prefix("gemini-")?.split_once(&format!("-{family}"))?;
+    let version = version.split('.').map(|i| i.parse().ok()).collect::<Option<Vec<u32>>>()?;
+    let revision = match rest {
+        "" => 0,
+        _ => rest.strip_prefix('-')?.parse().ok()?,
+    };
+    Some((version, revision))
+}
+
+/// A snapshot of generation defaults per model or model family, applied with
+/// [Conversation::apply_defaults]
+///
+/// Defaults come from two places: ones listed by the models endpoint (taken with
+/// [ModelDefaults::refresh] and saved with [ModelDefaults::get_real]), and overrides
+/// set in code with [ModelDefaults::set], which always win.
+/// ## Example:
+/// ```rust,no_run
+/// # use gemini_rs::{Conversation, GenerationParams, models::ModelDefaults};
+/// # async fn run() -> Result<(), gemini_rs::GeminiError<'static>> {
+/// let api_key = std::env::var("GEMINI_API_KEY").unwrap();
+/// let mut defaults = ModelDefaults::new();
+/// // Take the API's current defaults once and keep them in a file
+/// defaults.refresh(&api_key).await?;
+/// std::fs::write("defaults.json", defaults.get_real().dump())?;
+/// // A family applies to every model whose name starts with it
+/// defaults.set("gemini-1.5-pro", GenerationParams { temperature: Some(0.2), ..Default::default() });
+/// let mut convo = Conversation::new(api_key, "gemini-1.5-pro-002".to_string());
+/// convo.apply_defaults(&defaults);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct ModelDefaults {
+    overrides: Vec<(String, GenerationParams)>,
+    listed: Vec<(String, GenerationParams)>,
+} impl ModelDefaults {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Overrides the defaults for a model, or for every model starting with `family`.
+    /// Fields left `None` fall back to less specific entries, then to listed defaults.
+    pub fn set(&mut self, family: &str, params: GenerationParams) {
+        match self.overrides.iter_mut().find(|(name, _)| name == family) {
+            Some(entry) => entry.1 = params,
+            None => self.overrides.push((family.to_string(), params)),
+        }
+    }
+
+    /// The defaults for `model`, combining every matching entry with longer
+    /// (more specific) ones taking priority
+    pub fn get(&self, model: &str) -> Option<GenerationParams> {
+        let merge = |entries: &[(String, GenerationParams)]| {
+            let mut matching: Vec<&(String, GenerationParams)> = entries.iter()
+                .filter(|(family, _)| model.starts_with(family.as_str()))
+                .collect();
+            matching.sort_by_key(|(family, _)| std::cmp::Reverse(family.len()));
+            matching.into_iter().map(|(_, params)| *params).reduce(GenerationParams::or)
+        };
+        match (merge(&self.overrides), merge(&self.listed)) {
+            (Some(overrides), Some(listed)) => Some(overrides.or(listed)),
+            (overrides, listed) => overrides.or(listed),
+        }
+    }
+
+    /// Replaces the listed defaults with the ones the models endpoint has now
+    pub async fn refresh(&mut self, token: &str) -> Result<(), GeminiError<'static>> {
+        let request = reqwest::Client::new()
+            .get("https://generativelanguage.googleapis.com/v1beta/models?pageSize=1000")
+            .header(crate::API_KEY_HEADER, token)
+            .send().await?.text().await?;
+        let response_json = json::parse(&request)?;
+        check_api_error(&response_json)?;
+        self.listed = response_json["models"].members().map(|i| (
+            i["name"].as_str().unwrap_or("").trim_start_matches("models/").to_string(),
+            GenerationParams::get_fake(i),
+        )).collect();
+        Ok(())
+    }
+
+    /// The listed defaults, as an object of model names to `generationConfig` fields
+    pub fn get_real(&self) -> JsonValue {
+        let mut json = json::object! {};
+        for (family, params) in &self.listed {
+            json[family.as_str()] = params.get_real();
+        }
+        json
+    }
+
+    /// Loads listed defaults saved with [ModelDefaults::get_real]
+    pub fn get_fake(input: &JsonValue) -> ModelDefaults {
+        ModelDefaults {
+            overrides: vec![],
+            listed: input.entries().map(|(family, params)| (family.to_string(), GenerationParams::get_fake(params))).collect(),
+        }
+    }
+}
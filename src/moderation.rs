@@ -0,0 +1,130 @@
+//! Checks content against the safety filters with a cheap model before spending a
+//! request on an expensive one
+//!
+//! ## Example:
+//! ```rust,no_run
+//! # use gemini_rs::{Conversation, moderation::Moderator};
+//! # async fn run() -> Result<(), gemini_rs::GeminiError<'static>> {
+//! let api_key = std::env::var("GEMINI_API_KEY").unwrap();
+//! let moderator = Moderator::new(api_key.clone(), "gemini-1.5-flash-8b".to_string());
+//! let moderation = moderator.check("How do I bake bread?").await?;
+//! if moderation.is_allowed() {
+//!     // Send it to the expensive model
+//! }
+//! // Or screen every prompt of a conversation automatically
+//! let mut convo = Conversation::new(api_key, "gemini-1.5-pro".to_string());
+//! convo.set_input_guard(Some(moderator));
+//! # Ok(())
+//! # }
+//! ```
+use std::fmt;
+
+use crate::{
+    guard::{GuardFuture, InputGuard, Verdict},
+    response::FinishReason,
+    safety::{self, BlockReason, HarmBlockThreshold, SafetyRating},
+    Conversation, GeminiError, Part,
+};
+
+/// Whether content passed moderation
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModerationVerdict {
+    Allowed,
+    /// Blocked by the safety filters, with the reason the API gave if it gave one
+    Blocked(Option<BlockReason>),
+}
+
+/// The result of [Moderator::check]
+#[derive(Debug)]
+pub struct Moderation {
+    pub verdict: ModerationVerdict,
+    /// Ratings of the content from the API, empty if it didn't send any
+    pub safety_ratings: Vec<SafetyRating>,
+} impl Moderation {
+    pub fn is_allowed(&self) -> bool {
+        self.verdict == ModerationVerdict::Allowed
+    }
+}
+
+/// Sends content to a cheap model with strict safety settings and reports whether
+/// it got blocked
+pub struct Moderator {
+    token: String,
+    model: String,
+    threshold: HarmBlockThreshold,
+} impl fmt::Debug for Moderator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Moderator")
+            .field("token", &"[REDACTED]")
+            .field("model", &self.model)
+            .field("threshold", &self.threshold)
+            .finish()
+    }
+} impl Moderator {
+    /// Blocks content rated [HarmBlockThreshold::LowAndAbove] in any category
+    pub fn new(token: String, model: String) -> Self {
+        Self { token, model, threshold: HarmBlockThreshold::LowAndAbove }
+    }
+
+    pub fn threshold(mut self, threshold: HarmBlockThreshold) -> Self {
+        self.threshold = threshold;
+        self
+    }
+
+    /// Empty or whitespace-only text, like the text of a prompt made only of files,
+    /// is allowed without a request
+    pub async fn check(&self, text: &str) -> Result<Moderation, GeminiError<'static>> {
+        if text.trim().is_empty() {
+            return Ok(Moderation { verdict: ModerationVerdict::Allowed, safety_ratings: vec![] })
+        }
+        let mut convo = Conversation::new(self.token.clone(), self.model.clone());
+        convo.update_safety_settings(safety::safety_settings_from(self.threshold));
+        convo.set_system_instruction(Some("Reply with only OK."));
+        let response = convo.generate_content(vec![Part::Text(text.to_string())]).await?;
+
+        let mut safety_ratings = response.safety_rating;
+        let mut block_reason = None;
+        if let Some(feedback) = response.prompt_feedback {
+            block_reason = feedback.block_reason;
+            safety_ratings.extend(feedback.safety_ratings);
+        }
+        let blocked = block_reason.is_some()
+            || response.finish_reason == FinishReason::Safety
+            || safety_ratings.iter().any(|i| i.exceeds(self.threshold));
+        Ok(Moderation {
+            verdict: if blocked { ModerationVerdict::Blocked(block_reason) } else { ModerationVerdict::Allowed },
+            safety_ratings,
+        })
+    }
+} impl InputGuard for Moderator {
+    fn check<'a>(&'a self, text: &'a str) -> GuardFuture<'a> {
+        Box::pin(async move {
+            Ok(match Moderator::check(self, text).await?.verdict {
+                ModerationVerdict::Allowed => Verdict::Allow,
+                ModerationVerdict::Blocked(reason) => Verdict::Reject(match reason {
+                    Some(reason) => format!("Blocked by moderation ({reason:?})"),
+                    None => "Blocked by moderation".to_string(),
+                }),
+            })
+        })
+    }
+
+    fn is_remote(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn allows_empty_text_without_a_request() {
+        // An invalid key, so any request would fail
+        let moderator = Moderator::new(String::new(), "gemini-1.5-flash-8b".to_string());
+        for text in ["", "  \n\t"] {
+            assert_eq!(InputGuard::check(&moderator, text).await.unwrap(), Verdict::Allow);
+            assert!(moderator.check(text).await.unwrap().is_allowed());
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Converts history to and from OpenAI-style `[{role, content}]` message lists
+//!
+//! Handy for transcripts that are already stored in that shape. Only text is
+//! carried over, since the OpenAI-style format here has no room for files.
+use json::JsonValue;
+
+use crate::{GeminiError, Message, Part};
+
+/// A single `{role, content}` message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenAiMessage {
+    /// `user`, `assistant` or `system`
+    pub role: String,
+    pub content: String,
+} impl OpenAiMessage {
+    pub fn get_real(&self) -> JsonValue {
+        json::object! {
+            "role": self.role.clone(),
+            "content": self.content.clone()
+        }
+    }
+}
+
+impl From<&Message> for OpenAiMessage {
+    /// `model` becomes `assistant`, and all text parts are joined with newlines
+    fn from(message: &Message) -> Self {
+        let role = match message.role.as_str() {
+            "model" => "assistant",
+            role => role,
+        };
+        let content = message.content.iter().filter_map(|part| match part {
+            Part::Text(text) => Some(text.as_str()),
+            _ => None
+        }).collect::<Vec<_>>().join("\n");
+        Self {
+            role: role.to_string(),
+            content,
+        }
+    }
+}
+
+impl From<OpenAiMessage> for Message {
+    /// `assistant` becomes `model`, every other role (including `system`) becomes `user`
+    fn from(message: OpenAiMessage) -> Self {
+        let role = match message.role.as_str() {
+            "assistant" | "model" => "model",
+            _ => "user",
+        };
+        Message {
+            content: vec![Part::Text(message.content)],
+            role: role.to_string()
+        }
+    }
+}
+
+/// Converts a whole history into OpenAI-style messages
+pub fn to_openai(history: &[Message]) -> Vec<OpenAiMessage> {
+    history.iter().map(OpenAiMessage::from).collect()
+}
+
+/// Converts OpenAI-style messages into history, e.g. for [crate::Conversation::extend_history]
+pub fn from_openai(messages: Vec<OpenAiMessage>) -> Vec<Message> {
+    messages.into_iter().map(Message::from).collect()
+}
+
+/// Parses a JSON array of `{role, content}` objects into history
+pub fn from_openai_json(input: &str) -> Result<Vec<Message>, GeminiError<'static>> {
+    let json = json::parse(input)?;
+    if !json.is_array() {
+        return Err(GeminiError::ParseError("Expected an array of messages"))
+    }
+    let mut messages = vec![];
+    for i in json.members() {
+        messages.push(OpenAiMessage {
+            role: i["role"].as_str().unwrap_or("user").to_string(),
+            content: i["content"].as_str().unwrap_or("").to_string(),
+        });
+    }
+    Ok(from_openai(messages))
+}
+
+/// Formats a whole history as a JSON array of `{role, content}` objects
+pub fn to_openai_json(history: &[Message]) -> JsonValue {
+    JsonValue::Array(to_openai(history).iter().map(OpenAiMessage::get_real).collect())
+}
@@ -0,0 +1,238 @@
+//! Parsers for common shapes of model output
+//!
+//! Use them with [crate::response::GeminiResponse::parse_with], or call
+//! [OutputParser::parse] on any text.
+//! ## Example:
+//! ```rust
+//! # use gemini_rs::output::{BulletList, OutputParser};
+//! let items = BulletList.parse("Here you go:\n- apples\n* pears\n").unwrap();
+//! assert_eq!(items, vec!["apples", "pears"]);
+//! ```
+use json::JsonValue;
+
+use crate::GeminiError;
+
+/// Turns model output text into something more useful
+pub trait OutputParser {
+    type Output;
+
+    fn parse(&self, text: &str) -> Result<Self::Output, GeminiError<'static>>;
+}
+
+/// A fenced markdown code block
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeBlock {
+    /// The language after the opening fence, empty if there wasn't one
+    pub language: String,
+    pub code: String,
+}
+
+/// Extracts every fenced code block, optionally only ones in a specific language
+#[derive(Debug, Clone, Default)]
+pub struct CodeBlocks {
+    pub language: Option<String>,
+} impl OutputParser for CodeBlocks {
+    type Output = Vec<CodeBlock>;
+
+    fn parse(&self, text: &str) -> Result<Self::Output, GeminiError<'static>> {
+        Ok(code_blocks(text)
+            .into_iter()
+            .filter(|i| self.language.as_ref().is_none_or(|language| i.language.eq_ignore_ascii_case(language)))
+            .collect())
+    }
+}
+
+/// Extracts the items of a `-`, `*`, `•` or numbered list
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BulletList;
+
+impl OutputParser for BulletList {
+    type Output = Vec<String>;
+
+    fn parse(&self, text: &str) -> Result<Self::Output, GeminiError<'static>> {
+        let mut items = vec![];
+        for line in text.lines() {
+            let line = line.trim_start();
+            let item = if let Some(item) = line.strip_prefix(['-', '*', '•']) {
+                item
+            } else {
+                let digits = line.len() - line.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+                match line[digits..].strip_prefix(['.', ')']) {
+                    Some(item) if digits > 0 => item,
+                    _ => continue
+                }
+            };
+            if item.starts_with(char::is_whitespace) && !item.trim().is_empty() {
+                items.push(item.trim().to_string());
+            }
+        }
+        Ok(items)
+    }
+}
+
+/// Extracts `key: value` lines, in order. Lines without the separator are skipped.
+#[derive(Debug, Clone, Copy)]
+pub struct KeyValue {
+    pub separator: char,
+} impl Default for KeyValue {
+    fn default() -> Self {
+        Self { separator: ':' }
+    }
+} impl OutputParser for KeyValue {
+    type Output = Vec<(String, String)>;
+
+    fn parse(&self, text: &str) -> Result<Self::Output, GeminiError<'static>> {
+        Ok(text
+            .lines()
+            .filter_map(|line| line.split_once(self.separator))
+            .map(|(key, value)| (
+                key.trim().trim_start_matches(['-', '*']).trim().trim_matches('*').to_string(),
+                value.trim().to_string()
+            ))
+            .filter(|(key, _)| !key.is_empty())
+            .collect())
+    }
+}
+
+/// Finds the JSON in a response, even if it is wrapped in ```` ```json ```` fences
+/// or surrounded by prose
+#[derive(Debug, Clone, Copy, Default)]
+pub struct JsonOutput {
+    /// Only accept text that is valid JSON as a whole, without looking for it
+    pub strict: bool,
+} impl OutputParser for JsonOutput {
+    type Output = JsonValue;
+
+    fn parse(&self, text: &str) -> Result<Self::Output, GeminiError<'static>> {
+        if self.strict {
+            return Ok(json::parse(text)?)
+        }
+        let json = extract_json(text).ok_or(GeminiError::ParseError("No JSON found in response"))?;
+        Ok(json::parse(json)?)
+    }
+}
+
+/// Finds the first fenced JSON code block or balanced JSON object/array in `text`
+/// that parses successfully
+pub fn extract_json(text: &str) -> Option<&str> {
+    for block in code_block_ranges(text) {
+        let code = text[block.1.clone()].trim();
+        if json::parse(code).is_ok() {
+            return Some(code)
+        }
+    }
+    let mut start = 0;
+    while let Some(offset) = text[start..].find(['{', '[']) {
+        let begin = start + offset;
+        if let Some(end) = balanced_end(&text[begin..]) {
+            let candidate = &text[begin..begin + end];
+            if json::parse(candidate).is_ok() {
+                return Some(candidate)
+            }
+        }
+        start = begin + 1;
+    }
+    None
+}
+
+/// Length of the balanced `{...}`/`[...]` value at the start of `text`, ignoring
+/// brackets inside strings
+fn balanced_end(text: &str) -> Option<usize> {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in text.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue
+        }
+        match c {
+            '"' => in_string = true,
+            '{' | '[' => depth += 1,
+            '}' | ']' => {
+                depth -= 1;
+                if depth == 0 { return Some(i + 1) }
+            },
+            _ => {}
+        }
+    }
+    None
+}
+
+fn code_blocks(text: &str) -> Vec<CodeBlock> {
+    code_block_ranges(text)
+        .into_iter()
+        .map(|(language, code)| CodeBlock {
+            language: text[language].to_string(),
+            code: text[code].trim_end_matches('\n').to_string(),
+        })
+        .collect()
+}
+
+/// Byte ranges of the language and code of every fenced block
+fn code_block_ranges(text: &str) -> Vec<(std::ops::Range<usize>, std::ops::Range<usize>)> {
+    let mut blocks = vec![];
+    let mut start = 0;
+    while let Some(offset) = text[start..].find("```") {
+        let fence = start + offset + 3;
+        let Some(line_end) = text[fence..].find('\n').map(|i| fence + i) else { break };
+        let Some(close) = text[line_end + 1..].find("```").map(|i| line_end + 1 + i) else { break };
+        let language = text[fence..line_end].trim();
+        let language_start = fence + text[fence..line_end].find(language).unwrap_or(0);
+        blocks.push((language_start..language_start + language.len(), line_end + 1..close));
+        start = close + 3;
+    }
+    blocks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn extracts_nested_json() {
+        let text = r#"Sure! Here it is: {"user": {"name": "Jane", "tags": ["a", "{b}"]}, "ok": true} Hope that helps."#;
+        assert_eq!(extract_json(text), Some(r#"{"user": {"name": "Jane", "tags": ["a", "{b}"]}, "ok": true}"#));
+
+        let fenced = "```json\n[{\"id\": 1}, {\"id\": 2}]\n```";
+        assert_eq!(extract_json(fenced), Some("[{\"id\": 1}, {\"id\": 2}]"));
+    }
+
+    #[test]
+    fn skips_unbalanced_json() {
+        assert_eq!(extract_json(r#"Almost: {"a": [1, 2}"#), None);
+        // The unclosed object is skipped, the complete one after it is found
+        assert_eq!(extract_json(r#"{"broken": 1 and then {"fixed": 2}"#), Some(r#"{"fixed": 2}"#));
+        assert!(JsonOutput::default().parse("no json {here").is_err());
+    }
+
+    #[test]
+    fn filters_code_blocks_by_language() {
+        let text = "```python\nprint(1)\n```\nand\n```Rust\nfn main() {}\n```\n```\nplain\n```";
+        let rust = CodeBlocks { language: Some("rust".to_string()) }.parse(text).unwrap();
+        assert_eq!(rust, vec![CodeBlock { language: "Rust".to_string(), code: "fn main() {}".to_string() }]);
+
+        let all = CodeBlocks::default().parse(text).unwrap();
+        assert_eq!(all.len(), 3);
+        assert_eq!(all[2].language, "");
+    }
+
+    #[test]
+    fn parses_key_values() {
+        let text = "Summary:\n- **Name**: Jane\n* Role: admin: full\nno separator here\n: no key";
+        assert_eq!(KeyValue::default().parse(text).unwrap(), vec![
+            ("Summary".to_string(), String::new()),
+            ("Name".to_string(), "Jane".to_string()),
+            ("Role".to_string(), "admin: full".to_string()),
+        ]);
+        assert_eq!(
+            KeyValue { separator: '=' }.parse("a = 1\nb=2").unwrap(),
+            vec![("a".to_string(), "1".to_string()), ("b".to_string(), "2".to_string())]
+        );
+    }
+}
@@ -0,0 +1,88 @@
+//! A non-async facade for game loops and other sync code (requires the `polling` feature)
+//!
+//! Requests run on a background runtime and results are checked every frame with
+//! [Pending::try_recv], so nothing ever blocks the loop.
+//! ## Example:
+//! ```rust,no_run
+//! # use gemini_rs::{Conversation, Part, polling::Poller};
+//! let poller = Poller::new().unwrap();
+//! let npc = poller.conversation(Conversation::new(
+//!     std::env::var("GEMINI_API_KEY").unwrap(),
+//!     "gemini-1.5-flash".to_string()
+//! ));
+//! let mut reply = npc.send_message(vec![Part::Text("Greet the player".to_string())]);
+//! loop {
+//!     // update and draw the frame...
+//!     if let Some(response) = reply.try_recv() {
+//!         println!("{0}", response.unwrap());
+//!         break
+//!     }
+//! }
+//! ```
+use std::{io, sync::Arc};
+
+use tokio::{runtime::Runtime, sync::oneshot};
+
+use crate::{actor::ConversationActor, response::GeminiResponse, Conversation, GeminiError, Message, Part};
+
+/// Owns the background runtime that requests run on
+#[derive(Debug, Clone)]
+pub struct Poller {
+    runtime: Arc<Runtime>,
+} impl Poller {
+    /// Starts a runtime with a single worker thread
+    pub fn new() -> io::Result<Self> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .enable_all()
+            .build()?;
+        Ok(Self { runtime: Arc::new(runtime) })
+    }
+
+    /// Moves a conversation onto the background runtime
+    pub fn conversation(&self, conversation: Conversation) -> PollingConversation {
+        let _guard = self.runtime.enter();
+        PollingConversation {
+            actor: ConversationActor::spawn(conversation),
+            _runtime: self.runtime.clone(),
+        }
+    }
+}
+
+/// A conversation running in the background, see [crate::actor::ConversationActor]
+/// for how messages are handled
+#[derive(Debug, Clone)]
+pub struct PollingConversation {
+    actor: ConversationActor,
+    _runtime: Arc<Runtime>,
+} impl PollingConversation {
+    pub fn send_message(&self, input: Vec<Part>) -> Pending<Result<GeminiResponse, GeminiError<'static>>> {
+        Pending { receiver: self.actor.send_message(input) }
+    }
+
+    /// Stops the message being generated right now
+    pub fn cancel(&self) {
+        self.actor.cancel();
+    }
+
+    pub fn get_history(&self) -> Pending<Vec<Message>> {
+        Pending { receiver: self.actor.get_history() }
+    }
+}
+
+/// A result that isn't ready yet
+#[derive(Debug)]
+pub struct Pending<T> {
+    receiver: oneshot::Receiver<T>,
+} impl<T> Pending<T> {
+    /// The result if it is ready, otherwise `None`. Only returns it once.
+    pub fn try_recv(&mut self) -> Option<T> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Blocks until the result is ready, for loading screens and the like.
+    /// Must not be called from async code.
+    pub fn wait(self) -> Option<T> {
+        self.receiver.blocking_recv().ok()
+    }
+}
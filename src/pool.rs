@@ -0,0 +1,106 @@
+//! Runs many independent prompts concurrently, with bounded parallelism and an
+//! optional rate limit
+//!
+//! Useful for crawling or labeling pipelines that need throughput without
+//! going over quota.
+//! ## Example:
+//! ```rust,no_run
+//! # use gemini_rs::{Part, pool::ConversationPool};
+//! # async fn run() {
+//! let api_key = std::env::var("GEMINI_API_KEY").unwrap();
+//! let pool = ConversationPool::new(api_key, "gemini-1.5-flash".to_string(), 4)
+//!     .rate_limit(60);
+//! let handles: Vec<_> = ["cat", "dog", "fish"]
+//!     .iter()
+//!     .map(|animal| pool.submit(vec![Part::Text(format!("Describe a {animal} in 5 words"))]))
+//!     .collect();
+//! for handle in handles {
+//!     println!("{0}", handle.await.unwrap().unwrap());
+//! }
+//! # }
+//! ```
+use std::{fmt, sync::Arc, time::Duration};
+
+use tokio::{sync::{Mutex, Semaphore}, task::JoinHandle, time::Instant};
+
+use crate::{response::GeminiResponse, safety::SafetySetting, Conversation, GeminiError, Part};
+
+/// Sends prompts on up to N conversations at once, see the [module docs](self)
+pub struct ConversationPool {
+    token: String,
+    model: String,
+    safety_settings: Option<Vec<SafetySetting>>,
+    client: reqwest::Client,
+    permits: Arc<Semaphore>,
+    interval: Option<Duration>,
+    next_slot: Arc<Mutex<Instant>>,
+} impl fmt::Debug for ConversationPool {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConversationPool")
+            .field("token", &"[REDACTED]")
+            .field("model", &self.model)
+            .field("safety_settings", &self.safety_settings)
+            .field("available_permits", &self.permits.available_permits())
+            .field("interval", &self.interval)
+            .finish()
+    }
+}
+
+impl ConversationPool {
+    /// Creates a pool running at most `concurrency` requests at the same time
+    pub fn new(token: String, model: String, concurrency: usize) -> Self {
+        Self {
+            token,
+            model,
+            safety_settings: None,
+            client: reqwest::Client::new(),
+            permits: Arc::new(Semaphore::new(concurrency.max(1))),
+            interval: None,
+            next_slot: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Starts at most `requests_per_minute` requests per minute, spread out evenly
+    pub fn rate_limit(mut self, requests_per_minute: u32) -> Self {
+        self.interval = Some(Duration::from_secs(60) / requests_per_minute.max(1));
+        self
+    }
+
+    pub fn safety_settings(mut self, settings: Vec<SafetySetting>) -> Self {
+        self.safety_settings = Some(settings);
+        self
+    }
+
+    /// HTTP client shared by every request in the pool
+    pub fn http_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Queues a prompt on a fresh conversation. It starts once a slot is free and the
+    /// rate limit allows it. Must be called from inside a tokio runtime.
+    pub fn submit(&self, input: Vec<Part>) -> JoinHandle<Result<GeminiResponse, GeminiError<'static>>> {
+        let mut convo = Conversation::new(self.token.clone(), self.model.clone())
+            .with_http_client(self.client.clone());
+        if let Some(settings) = &self.safety_settings {
+            convo.update_safety_settings(settings.clone());
+        }
+        let permits = self.permits.clone();
+        let interval = self.interval;
+        let next_slot = self.next_slot.clone();
+
+        tokio::spawn(async move {
+            let _permit = permits.acquire_owned().await.expect("the pool's semaphore is never closed");
+            if let Some(interval) = interval {
+                let start = {
+                    let mut next_slot = next_slot.lock().await;
+                    let start = (*next_slot).max(Instant::now());
+                    *next_slot = start + interval;
+                    start
+                };
+                tokio::time::sleep_until(start).await;
+            }
+            convo.generate_content(input).await
+        })
+    }
+}
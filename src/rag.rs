@@ -0,0 +1,175 @@
+//! Embeddings and a small in-memory vector index, for answering questions from your
+//! own documents (retrieval augmented generation)
+//!
+//! ## Example:
+//! ```rust,no_run
+//! # use gemini_rs::{Conversation, rag::Retriever};
+//! # async fn run() -> Result<(), gemini_rs::GeminiError<'static>> {
+//! let api_key = std::env::var("GEMINI_API_KEY").unwrap();
+//! let mut retriever = Retriever::new(api_key.clone(), "text-embedding-004".to_string());
+//! retriever.add_documents(&[
+//!     "The office is open from 9 to 5 on weekdays.",
+//!     "Parking is free for visitors in lot B.",
+//! ]).await?;
+//! let mut convo = Conversation::new(api_key, "gemini-1.5-flash".to_string());
+//! let response = convo.retrieve_then_generate(&retriever, "Where can I park?", 1).await?;
+//! println!("{response}");
+//! # Ok(())
+//! # }
+//! ```
+use json::JsonValue;
+use reqwest::{Client, Method};
+
+use crate::{check_api_error, response::GeminiResponse, Conversation, GeminiError, Part};
+
+/// The most texts the API embeds in one request
+const MAX_BATCH: usize = 100;
+
+/// Embeds `text` with an embedding model like `text-embedding-004`
+pub async fn embed(token: &str, model: &str, text: &str) -> Result<Vec<f32>, GeminiError<'static>> {
+    Ok(embed_batch(token, model, &[text]).await?.remove(0))
+}
+
+/// Embeds every text, in as few requests as possible. The embeddings are in the same order.
+pub async fn embed_batch(token: &str, model: &str, texts: &[&str]) -> Result<Vec<Vec<f32>>, GeminiError<'static>> {
+    let client = Client::new();
+    let mut embeddings = vec![];
+    for chunk in texts.chunks(MAX_BATCH) {
+        let mut data = json::object! { "requests": [] };
+        for text in chunk {
+            data["requests"].push(json::object! {
+                "model": format!("models/{model}"),
+                "content": { "parts": [{ "text": *text }] }
+            })?;
+        }
+        let response_json = client
+            .request(Method::POST, format!(
+                "https://generativelanguage.googleapis.com/v1beta/models/{0}:batchEmbedContents",
+                model
+            ))
+            .header(crate::API_KEY_HEADER, token)
+            .header("Content-Type", "application/json")
+            .body(data.dump())
+            .send()
+            .await?
+            .text()
+            .await?;
+        let response_dict = json::parse(&response_json)?;
+        check_api_error(&response_dict)?;
+        for i in response_dict["embeddings"].members() {
+            embeddings.push(values(i));
+        }
+    }
+    if embeddings.len() != texts.len() {
+        return Err(GeminiError::ParseError("Failed to extract embeddings"))
+    }
+    Ok(embeddings)
+}
+
+fn values(embedding: &JsonValue) -> Vec<f32> {
+    embedding["values"].members().filter_map(|i| i.as_f32()).collect()
+}
+
+/// Cosine similarity of two embeddings, from -1 to 1. Embeddings of different
+/// lengths or all zeros have a similarity of 0.
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() { return 0.0 }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = a.iter().map(|x| x * x).sum::<f32>().sqrt() * b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm == 0.0 { 0.0 } else { dot / norm }
+}
+
+/// Texts and their embeddings, searched by cosine similarity
+#[derive(Debug, Clone, Default)]
+pub struct VectorStore {
+    entries: Vec<(String, Vec<f32>)>,
+} impl VectorStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add(&mut self, text: &str, embedding: Vec<f32>) {
+        self.entries.push((text.to_string(), embedding));
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// The `top_k` texts most similar to `embedding` with their similarity, best first
+    pub fn search(&self, embedding: &[f32], top_k: usize) -> Vec<(f32, &str)> {
+        let mut scored: Vec<(f32, &str)> = self.entries.iter()
+            .map(|(text, i)| (cosine_similarity(embedding, i), text.as_str()))
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        scored.truncate(top_k);
+        scored
+    }
+}
+
+/// A [VectorStore] that embeds documents and queries itself
+pub struct Retriever {
+    token: String,
+    model: String,
+    store: VectorStore,
+} impl std::fmt::Debug for Retriever {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Retriever")
+            .field("token", &"[REDACTED]")
+            .field("model", &self.model)
+            .field("documents", &self.store.len())
+            .finish()
+    }
+} impl Retriever {
+    /// Uses the embedding `model`, e.g. `text-embedding-004`
+    pub fn new(token: String, model: String) -> Self {
+        Self { token, model, store: VectorStore::new() }
+    }
+
+    /// Embeds and stores documents, e.g. chunks of longer texts
+    pub async fn add_documents(&mut self, documents: &[&str]) -> Result<(), GeminiError<'static>> {
+        let embeddings = embed_batch(&self.token, &self.model, documents).await?;
+        for (text, embedding) in documents.iter().zip(embeddings) {
+            self.store.add(text, embedding);
+        }
+        Ok(())
+    }
+
+    /// The `top_k` documents most similar to `query`, best first
+    pub async fn retrieve(&self, query: &str, top_k: usize) -> Result<Vec<(f32, &str)>, GeminiError<'static>> {
+        if self.store.is_empty() { return Ok(vec![]) }
+        let embedding = embed(&self.token, &self.model, query).await?;
+        Ok(self.store.search(&embedding, top_k))
+    }
+
+    pub fn store(&self) -> &VectorStore {
+        &self.store
+    }
+}
+
+impl Conversation {
+    /// Finds the `top_k` documents most relevant to `query` and prompts with them
+    /// followed by the query
+    pub async fn retrieve_then_generate(
+        &mut self,
+        retriever: &Retriever,
+        query: &str,
+        top_k: usize
+    ) -> Result<GeminiResponse, GeminiError<'static>> {
+        let documents = retriever.retrieve(query, top_k).await?;
+        let mut input = vec![];
+        if !documents.is_empty() {
+            let context: Vec<String> = documents.iter().map(|(_, text)| format!("---\n{text}")).collect();
+            input.push(Part::Text(format!(
+                "Answer using these documents where they are relevant:\n{0}\n---",
+                context.join("\n")
+            )));
+        }
+        input.push(Part::Text(query.to_string()));
+        self.generate_content(input).await
+    }
+}
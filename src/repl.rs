@@ -0,0 +1,154 @@
+//! A line based chat console, for the CLI or for a debug console in your own program
+//!
+//! Every line is sent as a prompt and the reply is written back whole. Lines starting
+//! with `/` are commands:
+//! - `/reset` clears the history
+//! - `/model [name]` shows or switches the model
+//! - `/system [text]` sets the system instruction, or removes it without text
+//! - `/help` lists the commands
+//! - `/exit` stops, like the end of the input does
+//! ## Example:
+//! ```rust,no_run
+//! # use gemini_rs::{Conversation, repl::Repl};
+//! # async fn run() {
+//! let mut convo = Conversation::new(
+//!     std::env::var("GEMINI_API_KEY").unwrap(),
+//!     "gemini-1.5-flash".to_string()
+//! );
+//! Repl::new(&mut convo)
+//!     .history_file("chat.json")
+//!     .run(std::io::stdin().lock(), std::io::stdout())
+//!     .await
+//!     .unwrap();
+//! # }
+//! ```
+use std::io::{self, BufRead, Write};
+
+use crate::{Conversation, GeminiError, Part};
+
+const HELP: &str = "/reset            clear the history
+/model [name]     show or switch the model
+/system [text]    set the system instruction, or remove it without text
+/help             show this list
+/exit             stop";
+
+/// Runs a chat console on a conversation, see the [module docs](self)
+#[derive(Debug)]
+pub struct Repl<'a> {
+    conversation: &'a mut Conversation,
+    history_file: Option<String>,
+    prompt: String,
+    attachments: Vec<Part>,
+}
+
+impl<'a> Repl<'a> {
+    pub fn new(conversation: &'a mut Conversation) -> Self {
+        Self {
+            conversation,
+            history_file: None,
+            prompt: "> ".to_string(),
+            attachments: vec![],
+        }
+    }
+
+    /// Loads the history from `path` if it exists, and saves it there after every
+    /// reply and `/reset`
+    pub fn history_file(mut self, path: &str) -> Self {
+        self.history_file = Some(path.to_string());
+        self
+    }
+
+    /// Text written before every line of input, `"> "` by default
+    pub fn prompt(mut self, prompt: &str) -> Self {
+        self.prompt = prompt.to_string();
+        self
+    }
+
+    /// Parts sent along with the first message, like files to ask about. They are sent
+    /// again with the next message if the first one fails.
+    pub fn attach(mut self, parts: Vec<Part>) -> Self {
+        self.attachments.extend(parts);
+        self
+    }
+
+    /// Reads lines from `input` until it ends or `/exit`. Errors from the API are
+    /// written to `output` and the console carries on; only IO errors stop it,
+    /// including a history file that can't be read or written.
+    pub async fn run(mut self, mut input: impl BufRead, mut output: impl Write) -> Result<(), GeminiError<'static>> {
+        if let Some(path) = &self.history_file {
+            if std::path::Path::new(path).exists() {
+                self.conversation.try_load(path).map_err(|e| history_error(path, e))?;
+            }
+        }
+        loop {
+            write!(output, "{0}", self.prompt)?;
+            output.flush()?;
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 { break }
+            let line = line.trim();
+            if line.is_empty() { continue }
+
+            if let Some(command) = line.strip_prefix('/') {
+                let (command, argument) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+                let argument = argument.trim();
+                match command {
+                    "exit" | "quit" => break,
+                    "reset" => {
+                        self.conversation.history.clear();
+                        self.save()?;
+                        writeln!(output, "History cleared")?;
+                    },
+                    "model" if argument.is_empty() => writeln!(output, "{0}", self.conversation.get_model())?,
+                    "model" => {
+                        self.conversation.set_model(argument.to_string());
+                        writeln!(output, "Switched to {argument}")?;
+                    },
+                    "system" => {
+                        self.conversation.set_system_instruction(Some(argument).filter(|i| !i.is_empty()));
+                        writeln!(output, "System instruction {0}", if argument.is_empty() { "removed" } else { "set" })?;
+                    },
+                    "help" => writeln!(output, "{HELP}")?,
+                    _ => writeln!(output, "Unknown command /{command}, try /help")?,
+                }
+                continue
+            }
+
+            // Cloning parts is cheap, inline data is shared
+            let mut parts = self.attachments.clone();
+            parts.push(Part::Text(line.to_string()));
+            match self.conversation.generate_content(parts).await {
+                Ok(response) => {
+                    // Attachments only go with the first message that gets a reply
+                    self.attachments.clear();
+                    writeln!(output, "{response}")?;
+                    self.save()?;
+                },
+                Err(e) => writeln!(output, "error: {e}")?,
+            }
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), GeminiError<'static>> {
+        match &self.history_file {
+            Some(path) => self.conversation.try_save(path).map_err(|e| history_error(path, e)),
+            None => Ok(())
+        }
+    }
+}
+
+/// Turns a failure to load or save the history file into an IO error naming the file
+fn history_error(path: &str, error: GeminiError<'static>) -> GeminiError<'static> {
+    match error {
+        GeminiError::IoError(e) => io::Error::new(e.kind(), format!("history file {path}: {e}")).into(),
+        e => io::Error::new(io::ErrorKind::InvalidData, format!("history file {path}: {e}")).into(),
+    }
+}
+
+impl Conversation {
+    /// Runs a chat console on this conversation, see [crate::repl]. Use [Repl] directly
+    /// to keep the history in a file or attach files to the first message.
+    pub async fn run_repl(&mut self, input: impl BufRead, output: impl Write) -> Result<(), GeminiError<'static>> {
+        Repl::new(self).run(input, output).await
+    }
+}
@@ -0,0 +1,204 @@
+//! Records responses and plays them back instead of calling the API, so tests of
+//! code built on a [Conversation] are deterministic and free
+//!
+//! ## Example:
+//! ```rust,no_run
+//! # use gemini_rs::{Conversation, replay::{ReplayKey, Transcript}};
+//! # async fn run() -> Result<(), gemini_rs::GeminiError<'static>> {
+//! // Once, against the real API
+//! let mut convo = Conversation::new(std::env::var("GEMINI_API_KEY").unwrap(), "gemini-1.5-flash".to_string());
+//! convo.record();
+//! convo.prompt("Hello").await;
+//! convo.take_recording().unwrap().save("tests/hello.json")?;
+//!
+//! // In tests, without an API key
+//! let mut convo = Conversation::new(String::new(), "gemini-1.5-flash".to_string());
+//! convo.replay_from(Transcript::load("tests/hello.json")?, ReplayKey::TurnIndex);
+//! convo.prompt("Hello").await;
+//! # Ok(())
+//! # }
+//! ```
+use std::{fs, path::Path};
+
+use json::JsonValue;
+
+use crate::{Conversation, GeminiError};
+
+/// How recorded responses are matched to requests
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayKey {
+    /// In the order they were recorded, whatever is sent
+    TurnIndex,
+    /// By a hash of the request body, so a changed prompt fails instead of silently
+    /// getting an old answer
+    RequestHash,
+}
+
+/// A recorded response and the hash of the request it answered
+#[derive(Debug, Clone)]
+pub struct TranscriptEntry {
+    pub request_hash: String,
+    /// The response as sent by the API, including error responses
+    pub response: JsonValue,
+}
+
+/// Responses recorded with [Conversation::record]
+#[derive(Debug, Clone, Default)]
+pub struct Transcript {
+    pub entries: Vec<TranscriptEntry>,
+} impl Transcript {
+    pub fn get_real(&self) -> JsonValue {
+        let mut entries = JsonValue::new_array();
+        for i in &self.entries {
+            let _ = entries.push(json::object! {
+                "requestHash": i.request_hash.as_str(),
+                "response": i.response.clone()
+            });
+        }
+        entries
+    }
+
+    pub fn get_fake(input: &JsonValue) -> Transcript {
+        Transcript {
+            entries: input.members().map(|i| TranscriptEntry {
+                request_hash: i["requestHash"].as_str().unwrap_or("").to_string(),
+                response: i["response"].clone(),
+            }).collect()
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), GeminiError<'static>> {
+        fs::write(path, self.get_real().pretty(2))?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Transcript, GeminiError<'static>> {
+        Ok(Self::get_fake(&json::parse(&fs::read_to_string(path)?)?))
+    }
+}
+
+/// Plays back a transcript
+#[derive(Debug, Clone)]
+pub(crate) struct Replayer {
+    transcript: Transcript,
+    key: ReplayKey,
+    next: usize,
+} impl Replayer {
+    /// The recorded response for a request with this body
+    pub(crate) fn respond(&mut self, body: &str) -> Result<JsonValue, GeminiError<'static>> {
+        let entry = match self.key {
+            ReplayKey::TurnIndex => self.transcript.entries.get(self.next),
+            ReplayKey::RequestHash => {
+                let hash = request_hash(body);
+                self.transcript.entries.iter().find(|i| i.request_hash == hash)
+            },
+        };
+        let Some(entry) = entry else {
+            return Err(GeminiError::ReplayError(match self.key {
+                ReplayKey::TurnIndex => format!("The transcript has no turn {0}", self.next),
+                ReplayKey::RequestHash => format!("No response was recorded for request {0}", request_hash(body)),
+            }))
+        };
+        self.next += 1;
+        Ok(entry.response.clone())
+    }
+}
+
+/// FNV-1a, which unlike the std hasher is stable between Rust versions
+pub(crate) fn request_hash(body: &str) -> String {
+    let hash = body.bytes().fold(0xcbf29ce484222325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    });
+    format!("{hash:016x}")
+}
+
+impl Conversation {
+    /// Starts recording every response, replacing any previous recording
+    pub fn record(&mut self) {
+        self.recording = Some(Transcript::default());
+    }
+
+    /// Stops recording and returns what was recorded
+    pub fn take_recording(&mut self) -> Option<Transcript> {
+        self.recording.take()
+    }
+
+    /// Answers every following request from `transcript` instead of the API. The
+    /// model and API key aren't checked while replaying.
+    pub fn replay_from(&mut self, transcript: Transcript, key: ReplayKey) {
+        self.replay = Some(Replayer { transcript, key, next: 0 });
+    }
+
+    /// Goes back to calling the API
+    pub fn stop_replay(&mut self) {
+        self.replay = None;
+    }
+
+    pub fn is_replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Part;
+
+    fn reply(text: &str) -> JsonValue {
+        json::object! {
+            "candidates": [{ "content": { "parts": [{ "text": text }], "role": "model" }, "finishReason": "STOP" }],
+            "usageMetadata": { "candidatesTokenCount": 1 }
+        }
+    }
+
+    fn entry(request_hash: &str, text: &str) -> TranscriptEntry {
+        TranscriptEntry { request_hash: request_hash.to_string(), response: reply(text) }
+    }
+
+    fn conversation() -> Conversation {
+        Conversation::new(String::new(), "gemini-1.5-flash".to_string())
+    }
+
+    async fn send(convo: &mut Conversation, text: &str) -> Result<String, GeminiError<'static>> {
+        convo.generate_content(vec![Part::Text(text.to_string())]).await.map(|i| i.into_text())
+    }
+
+    #[tokio::test]
+    async fn replays_by_turn_index() {
+        let transcript = Transcript { entries: vec![entry("", "first"), entry("", "second")] };
+        // Survives being saved and loaded
+        let transcript = Transcript::get_fake(&json::parse(&transcript.get_real().dump()).unwrap());
+        let mut convo = conversation();
+        convo.replay_from(transcript, ReplayKey::TurnIndex);
+        assert_eq!(send(&mut convo, "anything").await.unwrap(), "first");
+        assert_eq!(send(&mut convo, "something else").await.unwrap(), "second");
+        let error = send(&mut convo, "one more").await.unwrap_err();
+        assert!(matches!(&error, GeminiError::ReplayError(message) if message == "The transcript has no turn 2"));
+    }
+
+    #[tokio::test]
+    async fn replays_by_request_hash() {
+        let mut convo = conversation();
+        convo.replay_from(Transcript::default(), ReplayKey::RequestHash);
+        let error = send(&mut convo, "Hello").await.unwrap_err();
+        let GeminiError::ReplayError(message) = error else { panic!("expected a replay error, got {error:?}") };
+        let hash = message.strip_prefix("No response was recorded for request ").unwrap();
+        assert_eq!(hash.len(), 16);
+
+        let transcript = Transcript { entries: vec![entry("0000000000000000", "wrong"), entry(hash, "Hi!")] };
+        let mut convo = conversation();
+        convo.replay_from(transcript.clone(), ReplayKey::RequestHash);
+        assert_eq!(send(&mut convo, "Hello").await.unwrap(), "Hi!");
+
+        // A changed prompt doesn't get the old answer
+        let mut convo = conversation();
+        convo.replay_from(transcript, ReplayKey::RequestHash);
+        assert!(matches!(send(&mut convo, "Goodbye").await, Err(GeminiError::ReplayError(_))));
+    }
+
+    #[test]
+    fn hashes_are_stable() {
+        assert_eq!(request_hash(""), "cbf29ce484222325");
+        assert_eq!(request_hash("a"), "af63dc4c8601ec8c");
+    }
+}
@@ -1,6 +1,10 @@
-use crate::{safety, Part};
+use std::{fmt, path::{Path, PathBuf}};
 
-#[derive(Debug)]
+use json::JsonValue;
+
+use crate::{files, output::{JsonOutput, OutputParser}, safety, GeminiError, Message, Part};
+
+#[derive(Debug, PartialEq)]
 pub enum FinishReason {
     Unspecified,
     Stop,
@@ -38,13 +42,207 @@ pub struct GeminiResponse {
     pub safety_rating: Vec<safety::SafetyRating>,
     pub token_count: u64,
     pub finish_reason: FinishReason,
+    /// The id sent in the `X-Request-Id` header for this request
+    pub request_id: String,
+    pub prompt_feedback: Option<safety::PromptFeedback>,
+    /// Sources the response recites from, with the part of the text they apply to
+    pub citations: Vec<Citation>,
+    /// The exact model version that generated the response, e.g. `gemini-1.5-flash-002`
+    pub model_version: Option<String>,
+    /// The whole response as sent by the API, including fields this crate doesn't
+    /// parse yet (requires the `raw-response` feature)
+    #[cfg(feature = "raw-response")]
+    pub raw: JsonValue,
 } impl GeminiResponse {
+    /// Whether the prompt was blocked, in which case there is no content
+    pub fn is_blocked(&self) -> bool {
+        self.prompt_feedback.as_ref().is_some_and(|i| i.block_reason.is_some())
+    }
+
+    /// The text of the first part, or an empty string if it isn't text. Use
+    /// [GeminiResponse::text] to get all of the text.
     pub fn get_text(&self) -> String {
         //self.content[0].text.clone()
-        if let Part::Text(text) = &self.content[0] {
+        if let Some(Part::Text(text)) = self.content.first() {
             return text.to_string()
         };
         "".to_string()
     }
+
+    /// The text of the response with `[n]` markers after each cited passage, followed by
+    /// a numbered list of sources. Citations that point to the same source share a number.
+    pub fn cited_text(&self) -> String {
+        let text = self.text();
+        let mut sources: Vec<&str> = vec![];
+        let mut markers = vec![];
+        for i in &self.citations {
+            let source = i.uri.as_deref().or(i.title.as_deref()).unwrap_or("Unknown source");
+            let number = match sources.iter().position(|s| *s == source) {
+                Some(index) => index + 1,
+                None => { sources.push(source); sources.len() }
+            };
+            let mut end = i.end_index.min(text.len());
+            while !text.is_char_boundary(end) { end += 1 }
+            markers.push((end, number));
+        }
+        markers.sort();
+        markers.dedup();
+
+        let mut output = String::new();
+        let mut last = 0;
+        for (end, number) in markers {
+            output.push_str(&text[last..end]);
+            output.push_str(&format!("[{number}]"));
+            last = end;
+        }
+        output.push_str(&text[last..]);
+        if !sources.is_empty() {
+            output.push_str("\n\nSources:");
+            for (i, source) in sources.iter().enumerate() {
+                output.push_str(&format!("\n[{0}] {source}", i + 1));
+            }
+        }
+        output
+    }
+
+    /// All text parts joined together, skipping any media
+    pub fn text(&self) -> String {
+        self.to_string()
+    }
+
+    /// Like [GeminiResponse::text], but consumes the response
+    pub fn into_text(self) -> String {
+        Message { content: self.content, role: "model".to_string() }.into_text()
+    }
+
+    /// Renders the response as markdown for logs and transcripts. Text is kept as is,
+    /// images become image links to a placeholder named after the request id and part
+    /// (matching the file names from [GeminiResponse::save_media]), other media and
+    /// files become links.
+    pub fn to_markdown(&self) -> String {
+        let mut blocks = vec![];
+        for (i, part) in self.content.iter().enumerate() {
+            blocks.push(match part {
+                Part::Text(text) => text.trim_end().to_string(),
+                Part::InlineData(inline) => {
+                    let name = format!("{0}-{1}.{2}", self.request_id, i, files::mime_extension(&inline.mime_type));
+                    if inline.mime_type.starts_with("image/") {
+                        format!("![{0}]({name})", inline.mime_type)
+                    } else {
+                        format!("[{0}]({name})", inline.mime_type)
+                    }
+                },
+                Part::File(file) => format!("[{0}]({1})", file.mime_type, file.file_uri),
+            });
+        }
+        blocks.join("\n\n")
+    }
+
+    /// Runs the text of the response through a parser from [crate::output]
+    pub fn parse_with<P: OutputParser>(&self, parser: &P) -> Result<P::Output, GeminiError<'static>> {
+        parser.parse(&self.text())
+    }
+
+    /// Parses the JSON in the response, ignoring code fences or prose the model
+    /// added around it. Use [JsonOutput] with `strict` set to opt out of this.
+    pub fn json(&self) -> Result<JsonValue, GeminiError<'static>> {
+        self.parse_with(&JsonOutput::default())
+    }
+
+    /// Writes every inline media part (e.g. generated images or audio) into `dir`,
+    /// named after the request id with an extension based on the mime type.
+    /// Returns the paths of the written files.
+    pub fn save_media(&self, dir: impl AsRef<Path>) -> Result<Vec<PathBuf>, GeminiError<'static>> {
+        let mut paths = vec![];
+        for (i, part) in self.content.iter().enumerate() {
+            let Part::InlineData(inline) = part else { continue };
+            let path = dir.as_ref().join(format!(
+                "{0}-{1}.{2}",
+                self.request_id, i, files::mime_extension(&inline.mime_type)
+            ));
+            std::fs::write(&path, inline.decode()?)?;
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+}
+
+impl fmt::Display for GeminiResponse {
+    /// Writes every text part of the response, so `print!("{response}")` works even
+    /// when the first part isn't text or there is no content at all
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for part in &self.content {
+            if let Part::Text(text) = part {
+                f.write_str(text)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A source that part of a response was taken from
+#[derive(Debug, Clone)]
+pub struct Citation {
+    /// Byte offset where the cited passage starts in the response text
+    pub start_index: usize,
+    /// Byte offset where the cited passage ends
+    pub end_index: usize,
+    pub uri: Option<String>,
+    pub title: Option<String>,
+    pub license: Option<String>,
+} impl Citation {
+    pub fn get_fake(input: &JsonValue) -> Citation {
+        let string = |key: &str| input[key].as_str().map(str::to_string);
+        Citation {
+            start_index: input["startIndex"].as_usize().unwrap_or(0),
+            end_index: input["endIndex"].as_usize().unwrap_or(0),
+            uri: string("uri"),
+            title: string("title"),
+            license: string("license"),
+        }
+    }
+}
+
+/// Retries a prompt when Gemini stops because of [FinishReason::Recitation] or returns
+/// no content, set with [crate::Conversation::set_regeneration_policy]
+#[derive(Debug, Clone)]
+pub struct RegenerationPolicy {
+    /// How many extra requests can be made for a single prompt
+    pub max_retries: u32,
+    /// Sent after the prompt with every retry, e.g. asking Gemini to answer in its own
+    /// words. It isn't added to the history.
+    pub rephrase_instruction: Option<String>,
+    /// Added to the temperature for every retry, up to 2.0
+    pub temperature_bump: Option<f64>,
+} impl RegenerationPolicy {
+    pub fn new(max_retries: u32) -> Self {
+        Self {
+            max_retries,
+            rephrase_instruction: None,
+            temperature_bump: None,
+        }
+    }
+
+    pub fn with_rephrase_instruction(mut self, instruction: &str) -> Self {
+        self.rephrase_instruction = Some(instruction.to_string());
+        self
+    }
+
+    /// Raises the temperature by `step` on every retry, starting from the conversation's
+    /// temperature (or 1.0 if it doesn't set one), so retries are less likely to recite
+    /// the same text
+    pub fn with_temperature_bump(mut self, step: f64) -> Self {
+        self.temperature_bump = Some(step);
+        self
+    }
+
+    /// The temperature for retry number `retry` (starting at 1), `None` without a bump
+    pub fn retry_temperature(&self, base: Option<f64>, retry: u32) -> Option<f64> {
+        self.temperature_bump.map(|step| (base.unwrap_or(1.0) + step * retry as f64).clamp(0.0, 2.0))
+    }
+
+    pub fn should_regenerate(&self, response: &GeminiResponse) -> bool {
+        response.finish_reason == FinishReason::Recitation || response.content.is_empty()
+    }
 }
 
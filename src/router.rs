@@ -0,0 +1,183 @@
+//! Picks the model for a conversation based on what it is going to be used for
+//!
+//! Lets the choice of model (and its settings) live in one place instead of
+//! every [Conversation::new] call site.
+use std::{collections::HashMap, fmt};
+
+use crate::{response::RegenerationPolicy, safety::SafetySetting, Conversation, GenerationParams};
+
+/// The kind of work a conversation is created for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TaskClass {
+    /// Cheap and quick answers
+    Fast,
+    /// Harder questions that need a stronger model
+    Reasoning,
+    /// Prompts that include images or other files
+    Vision,
+}
+
+/// The model and default settings used for a [TaskClass]
+#[derive(Debug, Clone)]
+pub struct ModelRoute {
+    pub model: String,
+    /// Uses [crate::safety::default_safety_settings] when `None`
+    pub safety_settings: Option<Vec<SafetySetting>>,
+    pub regeneration_policy: Option<RegenerationPolicy>,
+    /// Fields left `None` come from [Router::default_generation_params]
+    pub generation_params: Option<GenerationParams>,
+    pub system_instruction: Option<String>,
+} impl ModelRoute {
+    pub fn new(model: &str) -> Self {
+        Self {
+            model: model.to_string(),
+            safety_settings: None,
+            regeneration_policy: None,
+            generation_params: None,
+            system_instruction: None,
+        }
+    }
+
+    pub fn with_safety_settings(mut self, settings: Vec<SafetySetting>) -> Self {
+        self.safety_settings = Some(settings);
+        self
+    }
+
+    pub fn with_regeneration_policy(mut self, policy: RegenerationPolicy) -> Self {
+        self.regeneration_policy = Some(policy);
+        self
+    }
+
+    pub fn with_generation_params(mut self, params: GenerationParams) -> Self {
+        self.generation_params = Some(params);
+        self
+    }
+
+    pub fn with_system_instruction(mut self, instruction: &str) -> Self {
+        self.system_instruction = Some(instruction.to_string());
+        self
+    }
+}
+
+/// Creates conversations with the right model for each [TaskClass]
+/// ## Example:
+/// ```rust
+/// # use gemini_rs::router::{Router, ModelRoute, TaskClass};
+/// let router = Router::new("ABC123".to_string())
+///     .route(TaskClass::Reasoning, ModelRoute::new("gemini-1.5-pro"));
+/// let convo = router.conversation(TaskClass::Reasoning);
+/// ```
+pub struct Router {
+    token: String,
+    routes: HashMap<TaskClass, ModelRoute>,
+    default_safety_settings: Option<Vec<SafetySetting>>,
+    default_regeneration_policy: Option<RegenerationPolicy>,
+    default_generation_params: Option<GenerationParams>,
+    default_system_instruction: Option<String>,
+    client: reqwest::Client,
+} impl fmt::Debug for Router {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Router")
+            .field("token", &"[REDACTED]")
+            .field("routes", &self.routes)
+            .field("default_safety_settings", &self.default_safety_settings)
+            .field("default_regeneration_policy", &self.default_regeneration_policy)
+            .field("default_generation_params", &self.default_generation_params)
+            .field("default_system_instruction", &self.default_system_instruction)
+            .finish()
+    }
+}
+
+impl Router {
+    /// Creates a router using `gemini-1.5-flash` for [TaskClass::Fast] and [TaskClass::Vision],
+    /// and `gemini-1.5-pro` for [TaskClass::Reasoning]
+    pub fn new(token: String) -> Self {
+        Self {
+            token,
+            routes: HashMap::from([
+                (TaskClass::Fast, ModelRoute::new("gemini-1.5-flash")),
+                (TaskClass::Reasoning, ModelRoute::new("gemini-1.5-pro")),
+                (TaskClass::Vision, ModelRoute::new("gemini-1.5-flash")),
+            ]),
+            default_safety_settings: None,
+            default_regeneration_policy: None,
+            default_generation_params: None,
+            default_system_instruction: None,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    /// HTTP client shared by every conversation the router creates, see
+    /// [Conversation::with_http_client]
+    pub fn http_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Safety settings for every route that doesn't set its own, so org-wide
+    /// policy can be enforced in one place
+    pub fn default_safety_settings(mut self, settings: Vec<SafetySetting>) -> Self {
+        self.default_safety_settings = Some(settings);
+        self
+    }
+
+    /// Regeneration policy for every route that doesn't set its own
+    pub fn default_regeneration_policy(mut self, policy: RegenerationPolicy) -> Self {
+        self.default_regeneration_policy = Some(policy);
+        self
+    }
+
+    /// Generation params for every route, a route's own params take precedence
+    /// field by field
+    pub fn default_generation_params(mut self, params: GenerationParams) -> Self {
+        self.default_generation_params = Some(params);
+        self
+    }
+
+    /// System instruction for every route that doesn't set its own
+    pub fn default_system_instruction(mut self, instruction: &str) -> Self {
+        self.default_system_instruction = Some(instruction.to_string());
+        self
+    }
+
+    /// Replaces the model and settings used for `class`
+    pub fn route(mut self, class: TaskClass, route: ModelRoute) -> Self {
+        self.routes.insert(class, route);
+        self
+    }
+
+    pub fn get_route(&self, class: TaskClass) -> &ModelRoute {
+        &self.routes[&class]
+    }
+
+    /// Starts a new conversation using the model and settings for `class`, falling back
+    /// to the router's defaults for anything the route doesn't set
+    pub fn conversation(&self, class: TaskClass) -> Conversation {
+        self.conversation_with_key(class, self.token.clone())
+    }
+
+    /// Same as [Router::conversation], but with a different API key, e.g. a customer's
+    /// own key. The router's settings and HTTP connection pool are still shared.
+    pub fn conversation_with_key(&self, class: TaskClass, token: String) -> Conversation {
+        let route = self.get_route(class);
+        let mut convo = Conversation::new(token, route.model.clone())
+            .with_http_client(self.client.clone());
+        if let Some(settings) = route.safety_settings.as_ref().or(self.default_safety_settings.as_ref()) {
+            convo.update_safety_settings(settings.clone());
+        }
+        convo.set_regeneration_policy(
+            route.regeneration_policy.clone().or(self.default_regeneration_policy.clone())
+        );
+        let params = match (route.generation_params, self.default_generation_params) {
+            (Some(params), Some(defaults)) => Some(params.or(defaults)),
+            (params, defaults) => params.or(defaults),
+        };
+        if let Some(params) = params {
+            convo.set_generation_params(params);
+        }
+        convo.set_system_instruction(
+            route.system_instruction.as_deref().or(self.default_system_instruction.as_deref())
+        );
+        convo
+    }
+}
@@ -2,9 +2,12 @@
 //!
 //! Is used to change what kind of messages will be blocked, as well as to show why
 //! a message was blocked
+use json::JsonValue;
+
+use crate::GeminiError;
 
 /// The category of a [SafetyRating]
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum HarmCategory {
     /// Category is unspecified
     Unspecified,
@@ -69,7 +72,8 @@ pub enum HarmCategory {
 ///
 /// The classification system gives the probability of the content being unsafe.
 /// This does not indicate the severity of harm for a piece of content.
-#[derive(Debug)]
+/// Ordered from [HarmProbability::Unspecified] to [HarmProbability::High]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum HarmProbability {
     /// Probability is unspecified
     Unspecified,
@@ -103,7 +107,9 @@ pub enum HarmProbability {
 }
 
 /// Block at and beyond a specified harm probability
-#[derive(Debug, Clone)]
+/// Ordered from the strictest ([HarmBlockThreshold::LowAndAbove]) to the most permissive
+/// ([HarmBlockThreshold::Off]), with [HarmBlockThreshold::Unspecified] first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum HarmBlockThreshold {
     /// Threshold is unspecified
     Unspecified,
@@ -118,6 +124,18 @@ pub enum HarmBlockThreshold {
     /// Turn off the safety filter.
     Off,
 } impl HarmBlockThreshold {
+    /// The lowest probability this threshold blocks, `None` if it blocks nothing.
+    /// [HarmBlockThreshold::Unspecified] leaves it to the API's default, so it is
+    /// treated as blocking nothing here.
+    pub fn lowest_blocked(&self) -> Option<HarmProbability> {
+        match self {
+            Self::LowAndAbove => Some(HarmProbability::Low),
+            Self::MediumAndAbove => Some(HarmProbability::Medium),
+            Self::OnlyHigh => Some(HarmProbability::High),
+            Self::Unspecified | Self::None | Self::Off => None,
+        }
+    }
+
     pub fn get_real(&self) -> &str {
         match self {
             Self::Unspecified => "HARM_BLOCK_THRESHOLD_UNSPECIFIED",
@@ -128,17 +146,57 @@ pub enum HarmBlockThreshold {
             Self::Off => "OFF",
         }
     }
+    pub fn get_fake(input: &str) -> HarmBlockThreshold {
+        match input {
+            "BLOCK_LOW_AND_ABOVE" => Self::LowAndAbove,
+            "BLOCK_MEDIUM_AND_ABOVE" => Self::MediumAndAbove,
+            "BLOCK_ONLY_HIGH" => Self::OnlyHigh,
+            "BLOCK_NONE" => Self::None,
+            "OFF" => Self::Off,
+            _ => Self::Unspecified,
+        }
+    }
 }
 
 /// Safety setting, affecting the safety-blocking behavior.
 /// 
 /// Passing a safety setting for a category changes the allowed probability that content is blocked.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct SafetySetting {
     pub category: HarmCategory,
     pub threshold: HarmBlockThreshold,
 }
 
+/// How harmful content is, as opposed to how likely it is to be harmful ([HarmProbability]).
+/// Ordered from [HarmSeverity::Unspecified] to [HarmSeverity::High].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HarmSeverity {
+    Unspecified,
+    Negligible,
+    Low,
+    Medium,
+    High,
+} impl HarmSeverity {
+    pub fn get_real(&self) -> &str {
+        match self {
+            Self::Unspecified => "HARM_SEVERITY_UNSPECIFIED",
+            Self::Negligible => "HARM_SEVERITY_NEGLIGIBLE",
+            Self::Low => "HARM_SEVERITY_LOW",
+            Self::Medium => "HARM_SEVERITY_MEDIUM",
+            Self::High => "HARM_SEVERITY_HIGH",
+        }
+    }
+    pub fn get_fake(input: &str) -> HarmSeverity {
+        match input {
+            "HARM_SEVERITY_NEGLIGIBLE" => HarmSeverity::Negligible,
+            "HARM_SEVERITY_LOW" => HarmSeverity::Low,
+            "HARM_SEVERITY_MEDIUM" => HarmSeverity::Medium,
+            "HARM_SEVERITY_HIGH" => HarmSeverity::High,
+            _ => HarmSeverity::Unspecified,
+        }
+    }
+}
+
 /// Safety rating for a piece of content.
 /// 
 /// The safety rating contains the category of harm and the harm probability level in that category for a piece of content.
@@ -148,29 +206,93 @@ pub struct SafetySetting {
 pub struct SafetyRating {
     pub category: HarmCategory,
     pub probability: HarmProbability,
+    /// The probability as a number from 0 to 1, only sent by some endpoints (e.g. Vertex AI)
+    pub probability_score: Option<f64>,
+    /// How harmful the content would be, only sent by some endpoints
+    pub severity: Option<HarmSeverity>,
+    /// The severity as a number from 0 to 1, only sent by some endpoints
+    pub severity_score: Option<f64>,
+} impl SafetyRating {
+    pub fn get_fake(input: &JsonValue) -> SafetyRating {
+        SafetyRating {
+            category: HarmCategory::get_fake(input["category"].as_str().unwrap_or("")),
+            probability: HarmProbability::get_fake(input["probability"].as_str().unwrap_or("")),
+            probability_score: input["probabilityScore"].as_f64(),
+            severity: input["severity"].as_str().map(HarmSeverity::get_fake),
+            severity_score: input["severityScore"].as_f64(),
+        }
+    }
+
+    /// Whether this rating would be blocked by `threshold`, for filtering responses
+    /// more strictly than the safety settings sent with the request
+    pub fn exceeds(&self, threshold: HarmBlockThreshold) -> bool {
+        threshold.lowest_blocked().is_some_and(|lowest| self.probability >= lowest)
+    }
+}
+
+/// Why a prompt was blocked before Gemini could answer it
+#[derive(Debug, Clone, PartialEq)]
+pub enum BlockReason {
+    Unspecified,
+    /// Blocked by the safety settings, see [PromptFeedback::safety_ratings]
+    Safety,
+    Other,
+    /// Contains terms from the terminology blocklist
+    Blocklist,
+    /// Contains prohibited content
+    ProhibitedContent,
+    /// Blocked because of unsafe image generation content
+    ImageSafety,
+} impl BlockReason {
+    pub fn get_fake(input: &str) -> BlockReason {
+        match input {
+            "SAFETY" => Self::Safety,
+            "OTHER" => Self::Other,
+            "BLOCKLIST" => Self::Blocklist,
+            "PROHIBITED_CONTENT" => Self::ProhibitedContent,
+            "IMAGE_SAFETY" => Self::ImageSafety,
+            _ => Self::Unspecified,
+        }
+    }
+}
+
+/// Feedback about the prompt itself, which is the only thing sent back when the
+/// prompt gets blocked
+#[derive(Debug)]
+pub struct PromptFeedback {
+    /// `None` if the prompt wasn't blocked
+    pub block_reason: Option<BlockReason>,
+    pub safety_ratings: Vec<SafetyRating>,
+} impl PromptFeedback {
+    pub fn get_fake(input: &JsonValue) -> PromptFeedback {
+        PromptFeedback {
+            block_reason: input["blockReason"].as_str().map(BlockReason::get_fake),
+            safety_ratings: input["safetyRatings"].members().map(SafetyRating::get_fake).collect(),
+        }
+    }
 }
 
 pub fn safety_settings_from(threshold: HarmBlockThreshold) -> Vec<SafetySetting> {
     vec![
         SafetySetting {
             category: HarmCategory::Harassment,
-            threshold: threshold.clone()
+            threshold
         },
         SafetySetting {
             category: HarmCategory::HateSpeech,
-            threshold: threshold.clone()
+            threshold
         },
         SafetySetting {
             category: HarmCategory::SexuallyExplicit,
-            threshold: threshold.clone()
+            threshold
         },
         SafetySetting {
             category: HarmCategory::DangerousContent,
-            threshold: threshold.clone()
+            threshold
         },
         SafetySetting {
             category: HarmCategory::CivicIntergrity,
-            threshold: threshold.clone()
+            threshold
         },
     ]
 }
@@ -179,3 +301,131 @@ pub fn safety_settings_from(threshold: HarmBlockThreshold) -> Vec<SafetySetting>
 pub fn default_safety_settings() -> Vec<SafetySetting> {
     safety_settings_from(HarmBlockThreshold::LowAndAbove)
 }
+
+/// Named sets of safety settings loaded from JSON, so policy can live in a config file.
+/// Only JSON is supported, convert TOML or YAML configs before loading them.
+/// ## Example:
+/// ```rust
+/// # use gemini_rs::safety::SafetyProfiles;
+/// let profiles = SafetyProfiles::parse(r#"{
+///     "kids": "BLOCK_LOW_AND_ABOVE",
+///     "internal-tool": {
+///         "HARM_CATEGORY_HARASSMENT": "BLOCK_ONLY_HIGH",
+///         "HARM_CATEGORY_DANGEROUS_CONTENT": "BLOCK_MEDIUM_AND_ABOVE"
+///     },
+///     "unfiltered": "OFF"
+/// }"#).unwrap();
+/// assert_eq!(profiles.profile("internal-tool").unwrap().len(), 2);
+/// assert!(profiles.profile("missing").is_err());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct SafetyProfiles {
+    profiles: Vec<(String, Vec<SafetySetting>)>,
+} impl SafetyProfiles {
+    /// Parses a JSON object of profile names to either a single threshold for every
+    /// category, an object of categories to thresholds, or an array in the API's
+    /// `safetySettings` format. Unknown categories or thresholds are an error.
+    pub fn parse(input: &str) -> Result<SafetyProfiles, GeminiError<'static>> {
+        let json = json::parse(input)?;
+        if !json.is_object() {
+            return Err(GeminiError::ConfigError("Safety profiles must be a JSON object".to_string()))
+        }
+        let mut profiles = vec![];
+        for (name, value) in json.entries() {
+            let settings = if let Some(threshold) = value.as_str() {
+                safety_settings_from(threshold_from_config(name, threshold)?)
+            } else if value.is_object() {
+                value.entries()
+                    .map(|(category, threshold)| setting_from_config(name, category, threshold.as_str().unwrap_or("")))
+                    .collect::<Result<_, _>>()?
+            } else if value.is_array() {
+                value.members()
+                    .map(|i| setting_from_config(
+                        name,
+                        i["category"].as_str().unwrap_or(""),
+                        i["threshold"].as_str().unwrap_or("")
+                    ))
+                    .collect::<Result<_, _>>()?
+            } else {
+                return Err(GeminiError::ConfigError(format!("Safety profile \"{name}\" has an invalid format")))
+            };
+            profiles.push((name.to_string(), settings));
+        }
+        Ok(SafetyProfiles { profiles })
+    }
+
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<SafetyProfiles, GeminiError<'static>> {
+        Self::parse(&std::fs::read_to_string(path)?)
+    }
+
+    /// The settings of the profile called `name`
+    pub fn profile(&self, name: &str) -> Result<Vec<SafetySetting>, GeminiError<'static>> {
+        self.profiles.iter()
+            .find(|(profile, _)| profile == name)
+            .map(|(_, settings)| settings.clone())
+            .ok_or_else(|| GeminiError::ConfigError(format!("No safety profile called \"{name}\"")))
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.profiles.iter().map(|(name, _)| name.as_str())
+    }
+}
+
+fn threshold_from_config(profile: &str, input: &str) -> Result<HarmBlockThreshold, GeminiError<'static>> {
+    let threshold = HarmBlockThreshold::get_fake(input);
+    if threshold.get_real() != input {
+        return Err(GeminiError::ConfigError(format!("Unknown threshold \"{input}\" in safety profile \"{profile}\"")))
+    }
+    Ok(threshold)
+}
+
+fn setting_from_config(profile: &str, category: &str, threshold: &str) -> Result<SafetySetting, GeminiError<'static>> {
+    let parsed = HarmCategory::get_fake(category);
+    if parsed.get_real() != category {
+        return Err(GeminiError::ConfigError(format!("Unknown category \"{category}\" in safety profile \"{profile}\"")))
+    }
+    Ok(SafetySetting { category: parsed, threshold: threshold_from_config(profile, threshold)? })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config_error(input: &str) -> String {
+        match SafetyProfiles::parse(input) {
+            Err(GeminiError::ConfigError(message)) => message,
+            other => panic!("expected a config error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_thresholds() {
+        assert_eq!(
+            config_error(r#"{"kids": "BLOCK_SOME"}"#),
+            "Unknown threshold \"BLOCK_SOME\" in safety profile \"kids\""
+        );
+        assert_eq!(
+            config_error(r#"{"tool": {"HARM_CATEGORY_HARASSMENT": "block_only_high"}}"#),
+            "Unknown threshold \"block_only_high\" in safety profile \"tool\""
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_categories() {
+        assert_eq!(
+            config_error(r#"{"tool": {"HARM_CATEGORY_GORE": "OFF"}}"#),
+            "Unknown category \"HARM_CATEGORY_GORE\" in safety profile \"tool\""
+        );
+        assert_eq!(
+            config_error(r#"{"tool": [{"threshold": "OFF"}]}"#),
+            "Unknown category \"\" in safety profile \"tool\""
+        );
+    }
+
+    #[test]
+    fn rejects_other_formats() {
+        assert!(matches!(SafetyProfiles::parse("kids = \"OFF\""), Err(GeminiError::JsonError(_))));
+        assert_eq!(config_error(r#"["OFF"]"#), "Safety profiles must be a JSON object");
+        assert_eq!(config_error(r#"{"kids": 3}"#), "Safety profile \"kids\" has an invalid format");
+    }
+}
@@ -1,13 +1,25 @@
 use std::{fs::File, io::{Read, Write}};
 
-use crate::{Conversation, Message, Part};
+use json::JsonValue;
+
+use crate::{safety::{HarmBlockThreshold, HarmCategory, SafetySetting}, Conversation, GeminiError, GenerationParams, Message, Part};
 
 impl Conversation {
+    /// Saves the history and metadata to `path` as JSON. Panics if it can't, see
+    /// [Conversation::try_save].
     pub fn save(&self, path: &str) {
-        let mut file = File::create(path).unwrap();
+        self.try_save(path).unwrap()
+    }
+
+    /// Same as [Conversation::save], but returns an error instead of panicking
+    pub fn try_save(&self, path: &str) -> Result<(), GeminiError<'static>> {
         let mut json = json::object! {
-            "history": []
+            "history": [],
+            "metadata": {}
         };
+        for (key, value) in self.metadata() {
+            json["metadata"][key.as_str()] = value.as_str().into();
+        }
         for i in self.history.iter() {
             let mut content = vec![];
             for part in i.content.iter() {
@@ -16,37 +28,149 @@ impl Conversation {
                     Part::File(file_data) => json::object! {
                         "file_uri": file_data.file_uri.clone(),
                         "mime_type": file_data.mime_type.clone()
+                    },
+                    Part::InlineData(inline) => json::object! {
+                        "data": &*inline.data,
+                        "mime_type": inline.mime_type.clone()
                     }
                 })
             };
             json["history"].push(json::object! {
                 "role": i.role.clone(),
                 "content": content
-            }).unwrap();
+            })?;
         }
-        let _ = file.write_all(json.dump().as_bytes());
+        let mut file = File::create(path)?;
+        file.write_all(json.dump().as_bytes())?;
+        Ok(())
     }
 
+    /// Replaces the history and metadata with the ones saved at `path`. Panics if
+    /// the file can't be read or isn't a saved conversation, see [Conversation::try_load].
     pub fn load(&mut self, path: &str) {
-        let mut file = File::open(path).unwrap();
+        self.try_load(path).unwrap()
+    }
+
+    /// Same as [Conversation::load], but returns an error instead of panicking. The
+    /// conversation is left as it was if loading fails.
+    pub fn try_load(&mut self, path: &str) -> Result<(), GeminiError<'static>> {
+        let mut file = File::open(path)?;
         let mut contents = String::new();
-        file.read_to_string(&mut contents).unwrap();
-        let json = json::parse(&contents).unwrap();
+        file.read_to_string(&mut contents)?;
+        let json = json::parse(&contents)?;
+        let field = |part: &JsonValue, key: &str| part[key]
+            .as_str()
+            .map(str::to_string)
+            .ok_or(GeminiError::ParseError("Saved conversation is missing a field"));
         let mut history: Vec<Message> = vec![];
         for i in json["history"].members() {
             let mut parts = vec![];
             for part in i["content"].members() {
                 if part.has_key("text") {
-                    parts.push(Part::Text(part["text"].as_str().unwrap().to_string()));
+                    parts.push(Part::Text(field(part, "text")?));
                 } else if part.has_key("file_uri") {
                     parts.push(Part::File(crate::files::GeminiFile {
-                        file_uri: part["file_uri"].as_str().unwrap().to_string(),
-                        mime_type: part["mime_type"].as_str().unwrap().to_string()
+                        file_uri: field(part, "file_uri")?,
+                        mime_type: field(part, "mime_type")?
+                    }));
+                } else if part.has_key("data") {
+                    parts.push(Part::InlineData(crate::files::InlineData {
+                        data: field(part, "data")?.into(),
+                        mime_type: field(part, "mime_type")?
                     }));
                 }
             }
-            history.push(Message { content: parts, role: i["role"].as_str().unwrap().to_string() });
+            history.push(Message { content: parts, role: field(i, "role")? });
         }
         self.history = history;
+        *self.metadata_mut() = json["metadata"].entries()
+            .filter_map(|(key, value)| Some((key.to_string(), value.as_str()?.to_string())))
+            .collect();
+        Ok(())
+    }
+
+    /// Exports the history as the API's `contents` JSON array, the same shape used
+    /// by the code exported from AI Studio
+    pub fn export_contents(&self) -> JsonValue {
+        let mut contents = JsonValue::new_array();
+        for i in self.history.iter() {
+            let _ = contents.push(i.get_real());
+        }
+        contents
+    }
+
+    /// Replaces the history with `contents` JSON, e.g. from AI Studio's "Get code".
+    ///
+    /// Accepts either the `contents` array itself or a whole request body containing it.
+    pub fn import_contents(&mut self, input: &str) -> Result<(), GeminiError<'static>> {
+        let json = json::parse(input)?;
+        let contents = if json.is_array() { &json } else { &json["contents"] };
+        if !contents.is_array() {
+            return Err(GeminiError::ParseError("Expected a contents array"))
+        }
+        self.history = contents.members().map(Message::get_fake).collect();
+        Ok(())
+    }
+
+    /// Creates a conversation from a prompt exported from AI Studio, either a saved
+    /// prompt file (with `runSettings` and `chunkedPrompt`) or the request body from
+    /// "Get code" (with `contents` and `safetySettings`).
+    ///
+    /// The model comes from the export if it has one, otherwise `model` is used.
+    /// The system instruction, safety settings, sampling settings (see [GenerationParams])
+    /// and stop sequences are applied. The rest of the generation config, like
+    /// response schemas, is ignored.
+    pub fn from_ai_studio(token: String, input: &str, model: Option<&str>) -> Result<Conversation, GeminiError<'static>> {
+        let json = json::parse(input)?;
+        let settings = if json.has_key("runSettings") { &json["runSettings"] } else { &json };
+
+        let model = settings["model"]
+            .as_str()
+            .or(model)
+            .ok_or(GeminiError::ModelError("The export has no model, please pass one"))?;
+        let mut convo = Conversation::new(token, model.trim_start_matches("models/").to_string());
+
+        if settings["safetySettings"].is_array() {
+            convo.update_safety_settings(settings["safetySettings"].members().map(|i| SafetySetting {
+                category: HarmCategory::get_fake(i["category"].as_str().unwrap_or("")),
+                threshold: HarmBlockThreshold::get_fake(i["threshold"].as_str().unwrap_or("")),
+            }).collect());
+        }
+
+        let config = if json.has_key("generationConfig") { &json["generationConfig"] } else { settings };
+        convo.set_generation_params(GenerationParams::get_fake(config));
+        if config["stopSequences"].is_array() {
+            let sequences: Vec<&str> = config["stopSequences"].members().filter_map(|i| i.as_str()).collect();
+            convo.set_stop_sequences(&sequences)?;
+        }
+
+        // Saved prompts have {"text": ...}, request bodies have {"parts": [...]}
+        let instruction = if json.has_key("systemInstruction") { &json["systemInstruction"] } else { &json["system_instruction"] };
+        let instruction = match instruction["text"].as_str() {
+            Some(text) => text.to_string(),
+            None => instruction["parts"].members().filter_map(|i| i["text"].as_str()).collect::<Vec<_>>().join("\n"),
+        };
+        if !instruction.is_empty() {
+            convo.set_system_instruction(Some(&instruction));
+        }
+
+        if json["chunkedPrompt"]["chunks"].is_array() {
+            // Saved prompts store one chunk per part, merge consecutive ones from the same role
+            let mut history: Vec<Message> = vec![];
+            for chunk in json["chunkedPrompt"]["chunks"].members() {
+                if chunk["isThought"].as_bool() == Some(true) { continue }
+                let role = chunk["role"].as_str().unwrap_or("user");
+                let parts = Message::get_fake(&json::object! { "parts": [chunk.clone()] }).content;
+                if parts.is_empty() { continue }
+                match history.last_mut() {
+                    Some(last) if last.role == role => last.content.extend(parts),
+                    _ => history.push(Message { content: parts, role: role.to_string() })
+                }
+            }
+            convo.history = history;
+        } else {
+            convo.import_contents(input)?;
+        }
+        Ok(convo)
     }
 }
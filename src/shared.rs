@@ -0,0 +1,56 @@
+//! A conversation that can be shared between tasks, e.g. web handlers
+//!
+//! Prompts sent at the same time are queued and sent one after the other, in the
+//! order they arrived, so each one sees the history including the replies before it.
+//! ## Example:
+//! ```rust,no_run
+//! # use gemini_rs::{Conversation, Part, shared::SharedConversation};
+//! # async fn run() {
+//! let convo = Conversation::new(std::env::var("GEMINI_API_KEY").unwrap(), "gemini-1.5-flash".to_string());
+//! let shared = SharedConversation::new(convo);
+//! let handle = shared.clone();
+//! tokio::spawn(async move {
+//!     handle.generate_content(vec![Part::Text("Hello".to_string())]).await
+//! });
+//! println!("{0}", shared.prompt("How are you?").await);
+//! # }
+//! ```
+use std::sync::Arc;
+
+use tokio::sync::{Mutex, MutexGuard};
+
+use crate::{response::GeminiResponse, Conversation, GeminiError, Message, Part};
+
+/// A cloneable handle to one conversation, see the [module docs](self)
+#[derive(Debug, Clone)]
+pub struct SharedConversation {
+    inner: Arc<Mutex<Conversation>>,
+} impl SharedConversation {
+    pub fn new(conversation: Conversation) -> Self {
+        Self { inner: Arc::new(Mutex::new(conversation)) }
+    }
+
+    /// Waits for the prompts queued before this one, then sends it
+    pub async fn generate_content(&self, input: Vec<Part>) -> Result<GeminiResponse, GeminiError<'static>> {
+        self.inner.lock().await.generate_content(input).await
+    }
+
+    /// Same as [Conversation::prompt], queued like [SharedConversation::generate_content]
+    pub async fn prompt(&self, input: &str) -> String {
+        match self.generate_content(vec![Part::Text(input.to_string())]).await {
+            Ok(i) => i.into_text(),
+            Err(e) => format!("{e}")
+        }
+    }
+
+    /// A copy of the history, once the queued prompts are done
+    pub async fn history(&self) -> Vec<Message> {
+        self.inner.lock().await.get_history().to_vec()
+    }
+
+    /// Exclusive access to the conversation, e.g. to change settings. Prompts from
+    /// other handles wait until the guard is dropped.
+    pub async fn lock(&self) -> MutexGuard<'_, Conversation> {
+        self.inner.lock().await
+    }
+}
@@ -0,0 +1,43 @@
+//! Metrics for every generateContent request, emitted through the `metrics` crate
+//! when the `metrics` feature is on
+//!
+//! - `gemini_requests_total` (counter, labels: `model`)
+//! - `gemini_errors_total` (counter, labels: `model`, `kind`)
+//! - `gemini_request_duration_seconds` (histogram, labels: `model`)
+//! - `gemini_output_tokens_total` (counter, labels: `model`)
+use std::time::Duration;
+
+use crate::{response::GeminiResponse, GeminiError};
+
+#[cfg(feature = "metrics")]
+pub(crate) fn record_request(model: &str, duration: Duration, result: &Result<GeminiResponse, GeminiError>) {
+    let model = model.to_string();
+    metrics::counter!("gemini_requests_total", "model" => model.clone()).increment(1);
+    metrics::histogram!("gemini_request_duration_seconds", "model" => model.clone()).record(duration.as_secs_f64());
+    match result {
+        Ok(response) => {
+            metrics::counter!("gemini_output_tokens_total", "model" => model).increment(response.token_count)
+        },
+        Err(e) => {
+            metrics::counter!("gemini_errors_total", "model" => model, "kind" => error_kind(e)).increment(1)
+        }
+    }
+}
+
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn record_request(_model: &str, _duration: Duration, _result: &Result<GeminiResponse, GeminiError>) {}
+
+#[cfg(feature = "metrics")]
+fn error_kind(error: &GeminiError) -> &'static str {
+    match error {
+        GeminiError::RequestError(_) | GeminiError::HttpError { .. } => "request",
+        GeminiError::IoError(_) => "io",
+        GeminiError::JsonError(_) => "json",
+        GeminiError::ParseError(_) => "parse",
+        GeminiError::ModelError(_) => "model",
+        GeminiError::KeyError(_) => "api",
+        GeminiError::ApiError(e) => e.status.get_real(),
+        GeminiError::QuotaError(_) => "quota",
+        _ => "other",
+    }
+}
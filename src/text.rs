@@ -0,0 +1,156 @@
+//! Splits long text into chunks for embedding or for fitting into a prompt
+//!
+//! Sizes are estimated the same way as [crate::estimate_tokens], one token per 4
+//! characters. Chunks can overlap so text cut at a boundary still has some context.
+//! ## Example:
+//! ```rust
+//! # use gemini_rs::text::split_by_sentences;
+//! let chunks = split_by_sentences("One. Two! Three? Four.", 4, 0);
+//! assert_eq!(chunks, vec!["One. Two! Three?", "Four."]);
+//! ```
+
+/// Splits on whitespace into chunks of at most `max_tokens`, repeating up to
+/// `overlap_tokens` worth of words from the end of each chunk at the start of the next
+pub fn split_by_tokens(text: &str, max_tokens: usize, overlap_tokens: usize) -> Vec<String> {
+    pack(&text.split_inclusive(char::is_whitespace).collect::<Vec<_>>(), max_tokens, overlap_tokens)
+}
+
+/// Splits between sentences (after `.`, `!` or `?` followed by whitespace, and at
+/// blank lines) into chunks of at most `max_tokens`. A single sentence longer than
+/// that gets a chunk of its own.
+pub fn split_by_sentences(text: &str, max_tokens: usize, overlap_tokens: usize) -> Vec<String> {
+    pack(&sentences(text), max_tokens, overlap_tokens)
+}
+
+/// Splits markdown at headings, ignoring `#` lines inside code blocks. Sections
+/// longer than `max_tokens` are split further by sentences, with the section's
+/// heading repeated at the start of every chunk.
+pub fn split_markdown_sections(text: &str, max_tokens: usize, overlap_tokens: usize) -> Vec<String> {
+    let mut sections: Vec<(Option<&str>, String)> = vec![(None, String::new())];
+    let mut in_code = false;
+    for line in text.lines() {
+        if line.trim_start().starts_with("```") {
+            in_code = !in_code;
+        }
+        if !in_code && line.starts_with('#') {
+            sections.push((Some(line), String::new()));
+            continue
+        }
+        let body = &mut sections.last_mut().expect("there is always a section").1;
+        body.push_str(line);
+        body.push('\n');
+    }
+
+    let mut chunks = vec![];
+    for (heading, body) in sections {
+        let heading = heading.map(|i| format!("{i}\n")).unwrap_or_default();
+        if body.trim().is_empty() && heading.is_empty() { continue }
+        let budget = max_tokens.saturating_sub(tokens(&heading)).max(1);
+        if tokens(&body) <= budget {
+            chunks.push(format!("{heading}{body}").trim().to_string());
+            continue
+        }
+        for chunk in split_by_sentences(&body, budget, overlap_tokens) {
+            chunks.push(format!("{heading}{chunk}"));
+        }
+    }
+    chunks
+}
+
+/// The sentences of `text`, each with the whitespace that follows it
+pub fn sentences(text: &str) -> Vec<&str> {
+    let mut sentences = vec![];
+    let mut start = 0;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let next = chars.peek().map(|(_, c)| *c);
+        let ends = match c {
+            '.' | '!' | '?' => next.is_none_or(char::is_whitespace),
+            '\n' => next == Some('\n'),
+            _ => false
+        };
+        if !ends { continue }
+        // Keep the whitespace after the sentence with it
+        let mut end = i + c.len_utf8();
+        while let Some((j, c)) = chars.peek().copied().filter(|(_, c)| c.is_whitespace()) {
+            end = j + c.len_utf8();
+            chars.next();
+        }
+        sentences.push(&text[start..end]);
+        start = end;
+    }
+    if start < text.len() {
+        sentences.push(&text[start..]);
+    }
+    sentences
+}
+
+fn tokens(text: &str) -> usize {
+    text.chars().count().div_ceil(4)
+}
+
+/// Joins consecutive units into chunks of at most `max_tokens`
+fn pack(units: &[&str], max_tokens: usize, overlap_tokens: usize) -> Vec<String> {
+    let mut chunks = vec![];
+    let mut start = 0;
+    while start < units.len() {
+        let mut end = start;
+        let mut size = 0;
+        while end < units.len() {
+            let next = tokens(units[end].trim_end());
+            if end > start && size + next > max_tokens { break }
+            size += next;
+            end += 1;
+        }
+        let chunk = units[start..end].concat().trim().to_string();
+        if !chunk.is_empty() {
+            chunks.push(chunk);
+        }
+        if end == units.len() { break }
+
+        // Step back over as many units as fit in the overlap, always moving forward
+        let mut next_start = end;
+        let mut overlap = 0;
+        while next_start > start + 1 {
+            let previous = tokens(units[next_start - 1].trim_end());
+            if overlap + previous > overlap_tokens { break }
+            overlap += previous;
+            next_start -= 1;
+        }
+        start = next_start;
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ignores_headings_in_code_blocks() {
+        let text = "# Setup\nRun this:\n```sh\n# not a heading\nls\n```\n## Usage\nJust run it.";
+        assert_eq!(split_markdown_sections(text, 100, 0), vec![
+            "# Setup\nRun this:\n```sh\n# not a heading\nls\n```",
+            "## Usage\nJust run it.",
+        ]);
+    }
+
+    #[test]
+    fn repeats_headings_in_long_sections() {
+        let text = "# Notes\nFirst sentence here. Second sentence here. Third sentence here.";
+        // 2 tokens go to the heading, leaving room for one 5 token sentence per chunk
+        assert_eq!(split_markdown_sections(text, 10, 0), vec![
+            "# Notes\nFirst sentence here.",
+            "# Notes\nSecond sentence here.",
+            "# Notes\nThird sentence here.",
+        ]);
+    }
+
+    #[test]
+    fn overlap_larger_than_budget_still_moves_forward() {
+        assert_eq!(split_by_tokens("a b c d e", 2, 10), vec!["a b", "b c", "c d", "d e"]);
+        assert_eq!(split_by_tokens("a b c", 1, 10), vec!["a", "b", "c"]);
+        let chunks = split_markdown_sections("# H\nOne. Two. Three.", 3, 50);
+        assert_eq!(chunks, vec!["# H\nOne. Two.", "# H\nTwo.", "# H\nThree."]);
+    }
+}
@@ -0,0 +1,146 @@
+//! Stores conversations as a tree, for chat UIs where a message can be edited
+//! or regenerated without losing the other branches
+//! ## Example:
+//! ```rust
+//! # use gemini_rs::{Message, Part, tree::ConversationTree};
+//! let mut tree = ConversationTree::new();
+//! let question = tree.add(None, Message {
+//!     content: vec![Part::Text("Hi!".to_string())],
+//!     role: "user".to_string()
+//! }, None);
+//! let first = tree.add(Some(question), Message {
+//!     content: vec![Part::Text("Hello!".to_string())],
+//!     role: "model".to_string()
+//! }, Some("gemini-1.5-flash"));
+//! let regenerated = tree.add(Some(question), Message {
+//!     content: vec![Part::Text("Hey there!".to_string())],
+//!     role: "model".to_string()
+//! }, Some("gemini-1.5-pro"));
+//!
+//! assert_eq!(tree.children(question).len(), 2);
+//! let convo = tree.to_conversation(regenerated, "ABC123".to_string(), "gemini-1.5-pro".to_string());
+//! ```
+use std::{collections::BTreeMap, fs, path::Path};
+
+use json::JsonValue;
+
+use crate::{Conversation, GeminiError, Message};
+
+/// A single message in a [ConversationTree]
+#[derive(Debug, Clone)]
+pub struct TreeNode {
+    pub id: usize,
+    /// `None` for the first message of a branch
+    pub parent: Option<usize>,
+    pub message: Message,
+    /// The model that wrote the message, `None` for user messages
+    pub model: Option<String>,
+    pub metadata: BTreeMap<String, String>,
+}
+
+/// Every version of a conversation, with each message pointing at the one before it
+#[derive(Debug, Clone, Default)]
+pub struct ConversationTree {
+    nodes: Vec<TreeNode>,
+} impl ConversationTree {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a message after `parent` and returns its id
+    pub fn add(&mut self, parent: Option<usize>, message: Message, model: Option<&str>) -> usize {
+        let id = self.nodes.len();
+        self.nodes.push(TreeNode {
+            id,
+            parent: parent.filter(|i| *i < id),
+            message,
+            model: model.map(str::to_string),
+            metadata: BTreeMap::new(),
+        });
+        id
+    }
+
+    pub fn get(&self, id: usize) -> Option<&TreeNode> {
+        self.nodes.get(id)
+    }
+
+    pub fn get_mut(&mut self, id: usize) -> Option<&mut TreeNode> {
+        self.nodes.get_mut(id)
+    }
+
+    pub fn nodes(&self) -> &[TreeNode] {
+        &self.nodes
+    }
+
+    /// Every message that directly follows `id`, e.g. the regenerated versions of a reply
+    pub fn children(&self, id: usize) -> Vec<&TreeNode> {
+        self.nodes.iter().filter(|i| i.parent == Some(id)).collect()
+    }
+
+    /// The messages from the start of the conversation up to and including `id`
+    pub fn path(&self, id: usize) -> Vec<&TreeNode> {
+        let mut path = vec![];
+        let mut current = self.nodes.get(id);
+        while let Some(node) = current {
+            path.push(node);
+            current = node.parent.and_then(|i| self.nodes.get(i));
+        }
+        path.reverse();
+        path
+    }
+
+    /// Creates a conversation with the history leading up to and including `id`
+    pub fn to_conversation(&self, id: usize, token: String, model: String) -> Conversation {
+        let mut convo = Conversation::new(token, model);
+        convo.extend_history(self.path(id).into_iter().map(|i| i.message.clone()).collect());
+        convo
+    }
+
+    pub fn get_real(&self) -> JsonValue {
+        let mut nodes = JsonValue::new_array();
+        for i in &self.nodes {
+            let mut metadata = JsonValue::new_object();
+            for (key, value) in &i.metadata {
+                metadata[key.as_str()] = value.as_str().into();
+            }
+            let _ = nodes.push(json::object! {
+                "id": i.id,
+                "parent": i.parent,
+                "message": i.message.get_real(),
+                "model": i.model.clone(),
+                "metadata": metadata
+            });
+        }
+        json::object! { "nodes": nodes }
+    }
+
+    pub fn get_fake(input: &JsonValue) -> Result<ConversationTree, GeminiError<'static>> {
+        let mut nodes = vec![];
+        for (index, i) in input["nodes"].members().enumerate() {
+            if i["id"].as_usize() != Some(index) {
+                return Err(GeminiError::ParseError("Tree nodes must be stored in id order"))
+            }
+            let mut metadata = BTreeMap::new();
+            for (key, value) in i["metadata"].entries() {
+                metadata.insert(key.to_string(), value.as_str().unwrap_or("").to_string());
+            }
+            nodes.push(TreeNode {
+                id: index,
+                parent: i["parent"].as_usize().filter(|parent| *parent < index),
+                message: Message::get_fake(&i["message"]),
+                model: i["model"].as_str().map(str::to_string),
+                metadata,
+            });
+        }
+        Ok(ConversationTree { nodes })
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), GeminiError<'static>> {
+        fs::write(path, self.get_real().dump())?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<ConversationTree, GeminiError<'static>> {
+        Self::get_fake(&json::parse(&fs::read_to_string(path)?)?)
+    }
+}